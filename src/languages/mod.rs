@@ -9,6 +9,7 @@ pub struct Syntax {
     pub builtins: &'static [&'static str],
     pub string_delimiter: &'static str,
     pub custom_separator: Option<&'static str>,
+    pub tab_stop: Option<usize>,
     pub flags: u32,
 }
 
@@ -16,6 +17,8 @@ pub const HIGHLIGHT_NUMBERS: u32 = 1 << 0;
 pub const HIGHLIGHT_STRINGS: u32 = 1 << 1;
 pub const HIGHLIGHT_CHARS: u32 = 1 << 2;
 pub const HIGHLIGHT_CASE_INSENSITIVE: u32 = 1 << 3;
+pub const SOFT_TABS: u32 = 1 << 4;
+pub const HIGHLIGHT_MARKDOWN: u32 = 1 << 5;
 
 pub const SYNTAX_C: Syntax = Syntax {
     name: "c",
@@ -33,6 +36,7 @@ pub const SYNTAX_C: Syntax = Syntax {
     builtins: &[],
     string_delimiter: "\"",
     custom_separator: None,
+    tab_stop: None,
     flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | HIGHLIGHT_CHARS,
 };
 
@@ -55,6 +59,7 @@ pub const SYNTAX_RUST: Syntax = Syntax {
     builtins: &[],
     string_delimiter: "\"",
     custom_separator: None,
+    tab_stop: Some(4),
     flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | HIGHLIGHT_CHARS,
 };
 
@@ -129,6 +134,7 @@ pub const SYNTAX_HASKELL: Syntax = Syntax {
     builtins: &[],
     string_delimiter: "\"",
     custom_separator: None,
+    tab_stop: None,
     flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | HIGHLIGHT_CHARS,
 };
 
@@ -217,7 +223,8 @@ pub const SYNTAX_PYTHON: Syntax = Syntax {
     ],
     string_delimiter: "\"'",
     custom_separator: None,
-    flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS,
+    tab_stop: Some(4),
+    flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | SOFT_TABS,
 };
 
 pub const SYNTAX_SHELL: Syntax = Syntax {
@@ -292,6 +299,7 @@ pub const SYNTAX_SHELL: Syntax = Syntax {
     ],
     string_delimiter: "\"'",
     custom_separator: None,
+    tab_stop: None,
     flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | HIGHLIGHT_CHARS,
 };
 
@@ -1285,6 +1293,7 @@ pub const SYNTAX_LISP: Syntax = Syntax {
     ],
     string_delimiter: "\"",
     custom_separator: Some("()"),
+    tab_stop: None,
     flags: HIGHLIGHT_NUMBERS
         | HIGHLIGHT_STRINGS
         | HIGHLIGHT_CHARS
@@ -1428,9 +1437,68 @@ pub const SYNTAX_CMAKE: Syntax = Syntax {
     ],
     string_delimiter: "\"",
     custom_separator: None,
+    tab_stop: None,
     flags: HIGHLIGHT_STRINGS | HIGHLIGHT_CASE_INSENSITIVE | HIGHLIGHT_CHARS,
 };
 
+pub const SYNTAX_JSON: Syntax = Syntax {
+    name: "json",
+    extensions: &[".json"],
+    single_line_comment: "",
+    multi_line_comment: ("", ""),
+    keywords: &["true", "false", "null"],
+    types: &[],
+    builtins: &[],
+    string_delimiter: "\"",
+    custom_separator: Some(",:-[]{}"),
+    tab_stop: None,
+    flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS,
+};
+
+pub const SYNTAX_TOML: Syntax = Syntax {
+    name: "toml",
+    extensions: &[".toml"],
+    single_line_comment: "#",
+    multi_line_comment: ("", ""),
+    keywords: &["true", "false"],
+    types: &[],
+    builtins: &[],
+    string_delimiter: "\"'",
+    custom_separator: None,
+    tab_stop: None,
+    flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS,
+};
+
+// NOTE: YAML doesn't allow tabs for indentation
+pub const SYNTAX_YAML: Syntax = Syntax {
+    name: "yaml",
+    extensions: &[".yaml", ".yml"],
+    single_line_comment: "#",
+    multi_line_comment: ("", ""),
+    keywords: &["true", "false"],
+    types: &[],
+    builtins: &[],
+    string_delimiter: "\"'",
+    custom_separator: None,
+    tab_stop: Some(2),
+    flags: HIGHLIGHT_NUMBERS | HIGHLIGHT_STRINGS | SOFT_TABS,
+};
+
+pub const SYNTAX_MARKDOWN: Syntax = Syntax {
+    name: "markdown",
+    extensions: &[".md", ".markdown"],
+    single_line_comment: "",
+    // NOTE: fenced code blocks are highlighted like comments
+    multi_line_comment: ("```", "```"),
+    keywords: &[],
+    types: &[],
+    builtins: &[],
+    string_delimiter: "`",
+    custom_separator: None,
+    tab_stop: None,
+    flags: HIGHLIGHT_STRINGS | HIGHLIGHT_MARKDOWN,
+};
+
 pub const SYNTAXES: &[Syntax] = &[
     SYNTAX_C,
     SYNTAX_RUST,
@@ -1439,7 +1507,15 @@ pub const SYNTAXES: &[Syntax] = &[
     SYNTAX_SHELL,
     SYNTAX_LISP,
     SYNTAX_CMAKE,
+    SYNTAX_JSON,
+    SYNTAX_TOML,
+    SYNTAX_YAML,
+    SYNTAX_MARKDOWN,
 ];
 
 #[cfg(test)]
 mod tests;
+
+pub fn find_syntax(name: &str) -> Option<&'static Syntax> {
+    SYNTAXES.iter().find(|syntax| syntax.name == name)
+}
@@ -1,48 +1,26 @@
-use std::io;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::{atomic::AtomicBool, Arc};
-use std::time::SystemTime;
 
+use crate::red_config::Config;
 use crate::Editor;
 use crate::Highlight;
 use crate::Row;
-use crate::SearchDirection;
-use crate::RED_QUIT_TIMES;
 use crate::RED_STATUS_HEIGHT;
 
 use super::{
-    Syntax, SYNTAX_C, SYNTAX_HASKELL, SYNTAX_LISP, SYNTAX_PYTHON, SYNTAX_RUST,
-    SYNTAX_SHELL,
+    Syntax, SYNTAX_C, SYNTAX_HASKELL, SYNTAX_JSON, SYNTAX_LISP,
+    SYNTAX_MARKDOWN, SYNTAX_PYTHON, SYNTAX_RUST, SYNTAX_SHELL, SYNTAX_TOML,
+    SYNTAX_YAML,
 };
 
-fn dummy_editor(syntax: &'static Syntax) -> Editor {
-    Editor {
-        original_termios: None,
-        cursor_x: 0,
-        cursor_y: 0,
-        render_x: 0,
-        screen_rows: 50 - RED_STATUS_HEIGHT,
-        screen_cols: 80,
-        editor_cols: 80,
-        row_offset: 0,
-        col_offset: 0,
-        rows: vec![],
-        file: None,
-        status_msg: String::new(),
-        status_time: SystemTime::UNIX_EPOCH,
-        dirty: false,
-        quit_times: RED_QUIT_TIMES,
-        search_dir: SearchDirection::Forward,
-        last_match: None,
-        win_changed: Arc::new(AtomicBool::new(false)),
-        stored_hl: None,
-        syntax: Some(syntax),
-        mark: None,
-        clipboard: String::new(),
-        stdin: Box::new(io::stdin()),
-        stdout: Box::new(io::stdout()),
-    }
+fn dummy_editor(syntax: &'static Syntax) -> Editor<'static, 'static> {
+    let mut editor = Editor::headless(Config::default());
+    editor.screen_rows = 50 - RED_STATUS_HEIGHT;
+    editor.screen_cols = 80;
+    editor.editor_cols = 80;
+    editor.window_size = || Ok((50, 60));
+    editor.buffer_mut().syntax = Some(syntax);
+    editor
 }
 
 fn hl_to_hldesc(highlights: &[Highlight]) -> String {
@@ -57,7 +35,9 @@ fn hl_to_hldesc(highlights: &[Highlight]) -> String {
             Highlight::Builtin => 'b',
             Highlight::String => 's',
             Highlight::Number => '0',
+            Highlight::Heading => 'h',
             Highlight::Match => 'm',
+            Highlight::MatchingBracket => 'B',
         })
         .collect()
 }
@@ -68,23 +48,27 @@ fn expect_highlight_lines(
     highlights: &[&str],
 ) {
     assert_eq!(lines.len(), highlights.len());
-    editor.rows.clear();
+    editor.buffer_mut().rows.clear();
 
     for ((n, line), highlight) in
         lines.iter().enumerate().zip(highlights.iter())
     {
-        editor.rows.push(Row {
-            index: n,
+        editor.buffer_mut().rows.push(Row {
             line: line.chars().collect(),
             render: vec![],
+            marks: vec![],
             highlights: vec![],
             in_comment: false,
+            stale: false,
         });
 
         editor.update_row(n);
         editor.update_syntax(n);
 
-        assert_eq!(hl_to_hldesc(&editor.rows[n].highlights), *highlight)
+        assert_eq!(
+            hl_to_hldesc(&editor.buffer().rows[n].highlights),
+            *highlight
+        )
     }
 }
 
@@ -240,6 +224,74 @@ fn test_syntax_lisp() {
     );
 }
 
+#[test]
+fn test_syntax_json() {
+    let mut editor = dummy_editor(&SYNTAX_JSON);
+
+    expect_highlight_line(
+        &mut editor,
+        r#"{"name": "red", "size": 42.5, "ok": true}"#,
+        r#"_ssssss__sssss__ssssss__0000__ssss__kkkk_"#,
+    );
+    expect_highlight_line(
+        &mut editor,
+        r#"["a\"b", -1, null, false]"#,
+        r#"_ssssss___0__kkkk__kkkkk_"#,
+    );
+}
+
+#[test]
+fn test_syntax_toml() {
+    let mut editor = dummy_editor(&SYNTAX_TOML);
+
+    expect_highlight_line(&mut editor, "[package]", "_________");
+    expect_highlight_line(
+        &mut editor,
+        r#"name = "red" # the name"#,
+        r#"_______sssss_cccccccccc"#,
+    );
+    expect_highlight_line(
+        &mut editor,
+        "opt-level = 3, lto = true",
+        "____________0________kkkk",
+    );
+    expect_highlight_line(&mut editor, "edition = '2018'", "__________ssssss");
+}
+
+#[test]
+fn test_syntax_yaml() {
+    let mut editor = dummy_editor(&SYNTAX_YAML);
+
+    expect_highlight_line(&mut editor, "on: [push]", "__________");
+    expect_highlight_line(
+        &mut editor,
+        "  fail-fast: false # keep going",
+        "_____________kkkkk_cccccccccccc",
+    );
+    expect_highlight_line(
+        &mut editor,
+        r#"  - run: "cargo test" 42"#,
+        r#"_________ssssssssssss_00"#,
+    );
+}
+
+#[test]
+fn test_syntax_markdown() {
+    let mut editor = dummy_editor(&SYNTAX_MARKDOWN);
+
+    expect_highlight_line(&mut editor, "# Title", "hhhhhhh");
+    expect_highlight_line(&mut editor, "### `code`", "hhhhhhhhhh");
+    expect_highlight_line(&mut editor, "#hashtag 42", "___________");
+    expect_highlight_line(&mut editor, "####### x", "_________");
+    expect_highlight_line(&mut editor, "run `cargo test`", "____ssssssssssss");
+
+    expect_highlight_lines(
+        &mut editor,
+        &["```rust", "# not a heading", "```", "## Heading"],
+        &["CCCCCCC", "CCCCCCCCCCCCCCC", "CCC", "hhhhhhhhhh"],
+    );
+}
+
 #[test]
 fn test_multiline_comment() {
     let mut editor = dummy_editor(&SYNTAX_RUST);
@@ -275,6 +327,40 @@ fn test_multiline_comment() {
     );
 }
 
+#[test]
+fn test_multiline_comment_cascade() {
+    let mut editor = dummy_editor(&SYNTAX_C);
+    editor.append_row(vec![]);
+    for _ in 0..20_000 {
+        editor.append_row("x".chars().collect());
+    }
+
+    // NOTE: every edit flips the comment state of the whole file, only the
+    // screen should be highlighted again
+    let screen_end = editor.screen_rows;
+    editor.buffer_mut().rows[10_000].highlights.clear();
+    for _ in 0..200 {
+        editor.buffer_mut().rows[0].line = "/*".chars().collect();
+        editor.update_row(0);
+        editor.buffer_mut().rows[0].line.clear();
+        editor.update_row(0);
+    }
+    assert!(editor.buffer().rows[screen_end].stale);
+    assert!(editor.buffer().rows[10_000].highlights.is_empty());
+
+    editor.buffer_mut().rows[0].line = "/*".chars().collect();
+    editor.update_row(0);
+    assert!(editor.buffer().rows[screen_end - 1].in_comment);
+    assert!(editor.buffer().rows[screen_end].stale);
+    assert!(!editor.buffer().rows[10_000].in_comment);
+
+    editor.buffer_mut().row_offset = 19_990;
+    editor.update_stale_rows(editor.buffer().row_offset + editor.screen_rows);
+    assert!(!editor.buffer().rows[screen_end].stale);
+    assert!(editor.buffer().rows.iter().all(|row| row.in_comment));
+    assert_eq!(hl_to_hldesc(&editor.buffer().rows[20_000].highlights), "C");
+}
+
 #[test]
 fn test_backslash_highlighting() {
     let mut editor = dummy_editor(&SYNTAX_C);
@@ -286,33 +372,45 @@ fn test_backslash_highlighting() {
 #[test]
 fn test_select_syntax() {
     let mut editor = dummy_editor(&SYNTAX_C);
-    editor.syntax = None;
+    editor.buffer_mut().syntax = None;
+
+    editor.buffer_mut().file = Some(PathBuf::from_str("main.c").unwrap());
+    editor.select_syntax_highlight();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_C));
+
+    editor.buffer_mut().file = Some(PathBuf::from_str("prog.rs").unwrap());
+    editor.select_syntax_highlight();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
+    assert!(!editor.soft_tabs);
 
-    editor.file = Some(PathBuf::from_str("main.c").unwrap());
+    editor.buffer_mut().file = Some(PathBuf::from_str("app.hs").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, Some(&SYNTAX_C));
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_HASKELL));
 
-    editor.file = Some(PathBuf::from_str("prog.rs").unwrap());
+    editor.buffer_mut().file = Some(PathBuf::from_str("script.py").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, Some(&SYNTAX_RUST));
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_PYTHON));
+    assert!(editor.soft_tabs);
 
-    editor.file = Some(PathBuf::from_str("app.hs").unwrap());
+    editor.buffer_mut().file = Some(PathBuf::from_str("start.sh").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, Some(&SYNTAX_HASKELL));
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_SHELL));
+    assert!(!editor.soft_tabs);
 
-    editor.file = Some(PathBuf::from_str("script.py").unwrap());
+    editor.buffer_mut().file = Some(PathBuf::from_str("README.md").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, Some(&SYNTAX_PYTHON));
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_MARKDOWN));
 
-    editor.file = Some(PathBuf::from_str("start.sh").unwrap());
+    editor.buffer_mut().file =
+        Some(PathBuf::from_str("notes.markdown").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, Some(&SYNTAX_SHELL));
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_MARKDOWN));
 
-    editor.file = Some(PathBuf::from_str("test.txt").unwrap());
+    editor.buffer_mut().file = Some(PathBuf::from_str("test.txt").unwrap());
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, None);
+    assert_eq!(editor.buffer().syntax, None);
 
-    editor.file = None;
+    editor.buffer_mut().file = None;
     editor.select_syntax_highlight();
-    assert_eq!(editor.syntax, None);
+    assert_eq!(editor.buffer().syntax, None);
 }
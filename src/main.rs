@@ -1,13 +1,18 @@
 #![warn(clippy::cognitive_complexity)]
 
-use libc::STDIN_FILENO;
+use libc::{STDIN_FILENO, STDOUT_FILENO};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::env;
 use std::error::Error;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::fs::{self, File};
+use std::io::{
+    self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write,
+};
 use std::iter::Enumerate;
+use std::ops::Range;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::path::{Path, PathBuf};
 use std::sync::{atomic, atomic::AtomicBool, Arc};
 use std::time::SystemTime;
@@ -15,19 +20,30 @@ use termios::{
     Termios, BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG, ISTRIP,
     IXON, OPOST, TCSAFLUSH, VMIN, VTIME,
 };
+use unicode_width::UnicodeWidthChar;
 
 mod languages;
+mod red_clipboard;
+mod red_config;
 mod red_error;
+mod red_history;
 mod red_ioctl;
-use languages::Syntax;
+mod red_positions;
+use languages::{find_syntax, Syntax};
 use languages::{
-    HIGHLIGHT_CASE_INSENSITIVE, HIGHLIGHT_CHARS, HIGHLIGHT_NUMBERS,
-    HIGHLIGHT_STRINGS, SYNTAXES,
+    HIGHLIGHT_CASE_INSENSITIVE, HIGHLIGHT_CHARS, HIGHLIGHT_MARKDOWN,
+    HIGHLIGHT_NUMBERS, HIGHLIGHT_STRINGS, SOFT_TABS, SYNTAXES,
+};
+use red_clipboard::{
+    osc52_copy, osc52_parse_reply, osc52_reply_range, OSC52_REQUEST,
 };
+use red_config::{glob_match, Config};
 use red_error::EditorError;
+use red_history::{text_end, Edit, History};
 use red_ioctl::get_window_size_ioctl;
 
 type Position = (usize, usize);
+type WindowSize = fn() -> Result<(usize, usize), Box<dyn Error>>;
 
 const ESC: char = '\x1b';
 const BACKSPACE: char = '\x7f';
@@ -38,8 +54,14 @@ const ESC_SEQ_BOTTOM_RIGHT: &[u8] = b"\x1b[999C\x1b[999B";
 const ESC_SEQ_QUERY_CURSOR: &[u8] = b"\x1b[6n";
 const ESC_SEQ_HIDE_CURSOR: &[u8] = b"\x1b[?25l";
 const ESC_SEQ_SHOW_CURSOR: &[u8] = b"\x1b[?25h";
+const ESC_SEQ_ENABLE_PASTE: &[u8] = b"\x1b[?2004h";
+const ESC_SEQ_DISABLE_PASTE: &[u8] = b"\x1b[?2004l";
+const ESC_SEQ_ENABLE_MOUSE: &[u8] = b"\x1b[?1002h\x1b[?1006h";
+const ESC_SEQ_DISABLE_MOUSE: &[u8] = b"\x1b[?1006l\x1b[?1002l";
 const ESC_SEQ_CLEAR_LINE: &[u8] = b"\x1b[K";
 const ESC_SEQ_INVERT_COLORS: &[u8] = b"\x1b[7m";
+const ESC_SEQ_DIM: &[u8] = b"\x1b[2m";
+const ESC_SEQ_NORMAL_INTENSITY: &[u8] = b"\x1b[22m";
 const ESC_SEQ_RESET_ALL: &[u8] = b"\x1b[m";
 const ESC_SEQ_COLOR_RED: &[u8] = b"\x1b[31m";
 const ESC_SEQ_COLOR_GREEN: &[u8] = b"\x1b[32m";
@@ -50,6 +72,8 @@ const ESC_SEQ_COLOR_CYAN: &[u8] = b"\x1b[36m";
 // const ESC_SEQ_COLOR_WHITE: &[u8] = b"\x1b[37m";
 const ESC_SEQ_COLOR_DEFAULT: &[u8] = b"\x1b[39m";
 const ESC_SEQ_COLOR_DEFAULT_BG: &[u8] = b"\x1b[49m";
+const ESC_SEQ_COLOR_RED_BG: &[u8] = b"\x1b[41m";
+const ESC_SEQ_COLOR_BRIGHT_MAGENTA: &[u8] = b"\x1b[95m";
 const ESC_SEQ_COLOR_BRIGHT_CYAN: &[u8] = b"\x1b[96m";
 const ESC_SEQ_COLOR_GRAY_BG: &[u8] = b"\x1b[100m";
 
@@ -58,10 +82,24 @@ fn esc_seq_move_cursor(pos_y: usize, pos_x: usize) -> Vec<u8> {
 }
 
 const RED_VERSION: &str = env!("CARGO_PKG_VERSION");
+const RED_USAGE: &str =
+    "usage: red [--follow] [--readonly] [--no-backup] [--theme NAME]
+           [--color=DEPTH] [+LINE] [FILE[:LINE[:COL]] [FILE]]
+       cmd | red [--stdout] [-]
+       red --cat [--theme NAME] [--color=DEPTH] FILE...
+
+themes: default, monochrome, high-contrast
+depths: 16, 256, truecolor (detected from COLORTERM and TERM)";
 const RED_TAB_STOP: usize = 8;
 const RED_QUIT_TIMES: u8 = 3;
 const RED_STATUS_HEIGHT: usize = 2;
+const RED_MIN_SCREEN_ROWS: usize = 1;
+const RED_MIN_SCREEN_COLS: usize = 10;
 const RED_LINE_SEP: &str = "│ ";
+const RED_PANE_SEP: &str = "│";
+const RED_WHEEL_LINES: usize = 3;
+const RED_TAB_MARKER: &str = "→";
+const RED_KILL_RING_SIZE: usize = 16;
 
 macro_rules! set_status_message {
     ($editor: expr, $($arg:tt)*) => {
@@ -69,7 +107,7 @@ macro_rules! set_status_message {
     };
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum EditorKey {
     ArrowLeft,
     ArrowRight,
@@ -80,11 +118,65 @@ enum EditorKey {
     PageDown,
     Home,
     End,
+    PasteStart,
+    PasteEnd,
+    BackTab,
+    ShiftArrowLeft,
+    ShiftArrowRight,
+    ShiftArrowUp,
+    ShiftArrowDown,
+    WheelUp,
+    WheelDown,
+    Mouse {
+        x: usize,
+        y: usize,
+        pressed: bool,
+        drag: bool,
+    },
     Ctrl(char),
     Meta(char),
     Other(char),
 }
 
+#[derive(Clone, PartialEq, Debug)]
+enum Change {
+    Insert(Vec<char>),
+    DeleteBackward(usize),
+    DeleteForward(usize),
+    Kill(usize),
+}
+
+impl EditorKey {
+    fn modifies_buffer(&self) -> bool {
+        matches!(
+            self,
+            EditorKey::Delete
+                | EditorKey::PasteStart
+                | EditorKey::BackTab
+                | EditorKey::Ctrl('h')
+                | EditorKey::Ctrl('i')
+                | EditorKey::Ctrl('k')
+                | EditorKey::Ctrl('m')
+                | EditorKey::Ctrl('/')
+                | EditorKey::Ctrl('r')
+                | EditorKey::Meta('r')
+                | EditorKey::Ctrl('s')
+                | EditorKey::Ctrl('v')
+                | EditorKey::Ctrl('w')
+                | EditorKey::Meta(BACKSPACE)
+                | EditorKey::Ctrl('y')
+                | EditorKey::Ctrl('z')
+                | EditorKey::Meta('z')
+                | EditorKey::Meta('.')
+                | EditorKey::Meta('d')
+                | EditorKey::Meta('y')
+                | EditorKey::Meta('n')
+                | EditorKey::Meta('p')
+                | EditorKey::Meta('t')
+        ) || matches!(self, EditorKey::Other(c) if *c != ESC)
+    }
+}
+
 enum SearchDirection {
     Forward,
     Backward,
@@ -114,11 +206,16 @@ impl SearchDirection {
 }
 
 struct Row {
-    index: usize,
     line: Vec<char>,
     render: Vec<char>,
+    // NOTE: combining marks take no cell of the render, they are drawn
+    // after the glyph in their column
+    marks: Vec<(usize, char)>,
     highlights: Vec<Highlight>,
     in_comment: bool,
+    // NOTE: the previous row changed its comment state after this row was
+    // highlighted, see `update_stale_rows`
+    stale: bool,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -131,103 +228,499 @@ enum Highlight {
     Builtin,
     String,
     Number,
+    Heading,
     Match,
+    MatchingBracket,
 }
 
 impl Highlight {
-    fn color(&self) -> &[u8] {
+    fn color<'t>(&self, theme: &'t Theme) -> &'t [u8] {
         #[allow(unreachable_patterns)]
         match self {
-            Highlight::Normal => ESC_SEQ_COLOR_DEFAULT,
-            Highlight::String => ESC_SEQ_COLOR_MAGENTA,
-            Highlight::Number => ESC_SEQ_COLOR_RED,
-            Highlight::Match => ESC_SEQ_COLOR_BLUE,
-            Highlight::Comment => ESC_SEQ_COLOR_CYAN,
-            Highlight::MultiLineComment => ESC_SEQ_COLOR_CYAN,
-            Highlight::Keyword => ESC_SEQ_COLOR_YELLOW,
-            Highlight::Type => ESC_SEQ_COLOR_GREEN,
-            Highlight::Builtin => ESC_SEQ_COLOR_BRIGHT_CYAN,
+            Highlight::Normal => &theme.normal,
+            Highlight::String => &theme.string,
+            Highlight::Number => &theme.number,
+            Highlight::Match => &theme.search_match,
+            Highlight::MatchingBracket => &theme.matching_bracket,
+            Highlight::Comment => &theme.comment,
+            Highlight::MultiLineComment => &theme.comment,
+            Highlight::Keyword => &theme.keyword,
+            Highlight::Type => &theme.type_,
+            Highlight::Builtin => &theme.builtin,
+            Highlight::Heading => &theme.heading,
+        }
+    }
+}
+
+// NOTE: Each sequence has to undo the attributes of the others, since only
+// the foreground color is reset at the end of a line.
+#[derive(Clone, PartialEq, Debug)]
+struct Theme {
+    name: &'static str,
+    normal: Cow<'static, [u8]>,
+    string: Cow<'static, [u8]>,
+    number: Cow<'static, [u8]>,
+    search_match: Cow<'static, [u8]>,
+    matching_bracket: Cow<'static, [u8]>,
+    comment: Cow<'static, [u8]>,
+    keyword: Cow<'static, [u8]>,
+    type_: Cow<'static, [u8]>,
+    builtin: Cow<'static, [u8]>,
+    heading: Cow<'static, [u8]>,
+    // NOTE: a background color
+    cursor_line: Cow<'static, [u8]>,
+}
+
+impl Theme {
+    fn with_depth(&self, depth: ColorDepth) -> Theme {
+        let convert = |color: &[u8]| Cow::Owned(convert_sgr(color, depth));
+        Theme {
+            name: self.name,
+            normal: convert(&self.normal),
+            string: convert(&self.string),
+            number: convert(&self.number),
+            search_match: convert(&self.search_match),
+            matching_bracket: convert(&self.matching_bracket),
+            comment: convert(&self.comment),
+            keyword: convert(&self.keyword),
+            type_: convert(&self.type_),
+            builtin: convert(&self.builtin),
+            heading: convert(&self.heading),
+            cursor_line: convert(&self.cursor_line),
+        }
+    }
+
+    // NOTE: see `COLOR_KEYS` for the names
+    fn with_colors(&self, colors: &[(String, String)]) -> Theme {
+        let mut theme = self.clone();
+        for (key, sgr) in colors {
+            let color = match key.as_str() {
+                "normal" => &mut theme.normal,
+                "string" => &mut theme.string,
+                "number" => &mut theme.number,
+                "match" => &mut theme.search_match,
+                "matching_bracket" => &mut theme.matching_bracket,
+                "comment" => &mut theme.comment,
+                "keyword" => &mut theme.keyword,
+                "type" => &mut theme.type_,
+                "builtin" => &mut theme.builtin,
+                "heading" => &mut theme.heading,
+                _ => continue,
+            };
+            *color = Cow::Owned(format!("\x1b[22;24;{}m", sgr).into_bytes());
+        }
+        theme
+    }
+}
+
+const THEMES: &[Theme] = &[
+    Theme {
+        name: "default",
+        normal: Cow::Borrowed(ESC_SEQ_COLOR_DEFAULT),
+        string: Cow::Borrowed(ESC_SEQ_COLOR_MAGENTA),
+        number: Cow::Borrowed(ESC_SEQ_COLOR_RED),
+        search_match: Cow::Borrowed(ESC_SEQ_COLOR_BLUE),
+        matching_bracket: Cow::Borrowed(ESC_SEQ_COLOR_BRIGHT_MAGENTA),
+        comment: Cow::Borrowed(ESC_SEQ_COLOR_CYAN),
+        keyword: Cow::Borrowed(ESC_SEQ_COLOR_YELLOW),
+        type_: Cow::Borrowed(ESC_SEQ_COLOR_GREEN),
+        builtin: Cow::Borrowed(ESC_SEQ_COLOR_BRIGHT_CYAN),
+        heading: Cow::Borrowed(ESC_SEQ_COLOR_YELLOW),
+        cursor_line: Cow::Borrowed(b"\x1b[40m"),
+    },
+    Theme {
+        name: "monochrome",
+        normal: Cow::Borrowed(b"\x1b[22;24m"),
+        string: Cow::Borrowed(b"\x1b[22;24m"),
+        number: Cow::Borrowed(b"\x1b[22;24m"),
+        search_match: Cow::Borrowed(b"\x1b[22;4m"),
+        matching_bracket: Cow::Borrowed(b"\x1b[22;4m"),
+        comment: Cow::Borrowed(b"\x1b[24;2m"),
+        keyword: Cow::Borrowed(b"\x1b[24;1m"),
+        type_: Cow::Borrowed(b"\x1b[24;1m"),
+        builtin: Cow::Borrowed(b"\x1b[22;24m"),
+        heading: Cow::Borrowed(b"\x1b[24;1m"),
+        cursor_line: Cow::Borrowed(b"\x1b[40m"),
+    },
+    Theme {
+        name: "high-contrast",
+        normal: Cow::Borrowed(b"\x1b[22;39m"),
+        string: Cow::Borrowed(b"\x1b[22;95m"),
+        number: Cow::Borrowed(b"\x1b[22;91m"),
+        search_match: Cow::Borrowed(b"\x1b[1;94m"),
+        matching_bracket: Cow::Borrowed(b"\x1b[1;95m"),
+        comment: Cow::Borrowed(b"\x1b[22;96m"),
+        keyword: Cow::Borrowed(b"\x1b[1;93m"),
+        type_: Cow::Borrowed(b"\x1b[1;92m"),
+        builtin: Cow::Borrowed(b"\x1b[22;94m"),
+        heading: Cow::Borrowed(b"\x1b[1;93m"),
+        cursor_line: Cow::Borrowed(b"\x1b[40m"),
+    },
+];
+
+fn find_theme(name: &str) -> Option<&'static Theme> {
+    THEMES.iter().find(|theme| theme.name == name)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ColorDepth {
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    fn parse(name: &str) -> Option<ColorDepth> {
+        match name {
+            "16" => Some(ColorDepth::Basic),
+            "256" => Some(ColorDepth::Ansi256),
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            _ => None,
+        }
+    }
+
+    // NOTE: there is no reliable way to ask the terminal, so this follows
+    // the usual conventions of COLORTERM and TERM
+    fn detect(colorterm: &str, term: &str) -> ColorDepth {
+        if matches!(colorterm, "truecolor" | "24bit") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Basic
         }
     }
+
+    fn from_env() -> ColorDepth {
+        ColorDepth::detect(
+            &env::var("COLORTERM").unwrap_or_default(),
+            &env::var("TERM").unwrap_or_default(),
+        )
+    }
+}
+
+// NOTE: the usual (xterm) values of the 16 basic colors, the terminal may use
+// its own palette for them
+const BASIC_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+// NOTE: the nearest color of the 6x6x6 cube of the 256 colors
+fn rgb_to_ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    let level = |v: u8| if v < 48 { 0 } else { (v.max(75) - 35) / 40 };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+// NOTE: the nearest of the basic colors for terminals without truecolor
+fn rgb_to_basic((r, g, b): (u8, u8, u8)) -> usize {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        [(r, pr), (g, pg), (b, pb)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    let (index, _) = BASIC_PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance(color))
+        .unwrap();
+    index
+}
+
+fn rgb_to_sgr(
+    rgb: (u8, u8, u8),
+    depth: ColorDepth,
+    background: bool,
+) -> String {
+    let (extended, basic) = if background { (48, 40) } else { (38, 30) };
+    match depth {
+        ColorDepth::TrueColor => {
+            format!("{};2;{};{};{}", extended, rgb.0, rgb.1, rgb.2)
+        }
+        ColorDepth::Ansi256 => {
+            format!("{};5;{}", extended, rgb_to_ansi256(rgb))
+        }
+        ColorDepth::Basic => match rgb_to_basic(rgb) {
+            index @ 0..=7 => (basic + index).to_string(),
+            index => (basic + 60 + index - 8).to_string(),
+        },
+    }
+}
+
+// NOTE: Replaces the truecolor colors of an SGR sequence with the nearest ones
+// the terminal supports. The basic colors are left to the terminal's palette.
+fn convert_sgr(sequence: &[u8], depth: ColorDepth) -> Vec<u8> {
+    let params = sequence
+        .strip_prefix(b"\x1b[")
+        .and_then(|params| params.strip_suffix(b"m"))
+        .and_then(|params| str::from_utf8(params).ok());
+    let params = match params {
+        Some(params) => params.split(';').collect::<Vec<_>>(),
+        None => return sequence.to_vec(),
+    };
+
+    let mut codes = vec![];
+    let mut idx = 0;
+    while idx < params.len() {
+        let code = params[idx].parse::<usize>().ok();
+        let (rgb, background) = match (code, &params[idx + 1..]) {
+            (Some(code @ (38 | 48)), ["2", r, g, b, ..]) => {
+                match (r.parse(), g.parse(), b.parse()) {
+                    (Ok(r), Ok(g), Ok(b)) => {
+                        idx += 4;
+                        ((r, g, b), code == 48)
+                    }
+                    _ => return sequence.to_vec(),
+                }
+            }
+            // NOTE: other extended colors are kept as they are
+            (Some(38 | 48), _) => {
+                codes.extend(params[idx..].iter().map(|p| p.to_string()));
+                break;
+            }
+            _ => {
+                codes.push(params[idx].to_owned());
+                idx += 1;
+                continue;
+            }
+        };
+        codes.push(rgb_to_sgr(rgb, depth, background));
+        idx += 1;
+    }
+
+    format!("\x1b[{}m", codes.join(";")).into_bytes()
 }
 
 impl Row {
-    fn empty(at: usize) -> Row {
+    fn empty() -> Row {
         Row {
-            index: at,
             line: vec![],
             render: vec![],
+            marks: vec![],
             highlights: vec![],
             in_comment: false,
+            stale: false,
         }
     }
 }
 
-struct Editor<'i, 'o> {
-    original_termios: Option<Termios>,
+#[derive(Default)]
+struct Buffer {
+    rows: Vec<Row>,
+    file: Option<PathBuf>,
+    dirty: bool,
     cursor_x: usize,
     cursor_y: usize,
+    row_offset: usize,
+    col_offset: usize,
+    syntax: Option<&'static Syntax>,
+    mark: Option<Position>,
+    follow: Option<Follow>,
+    history: History,
+    line_ending: LineEnding,
+    mixed_line_endings: bool,
+    missing_final_newline: bool,
+    invalid_utf8: bool,
+    disk_mtime: Option<SystemTime>,
+    made_backup: bool,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+struct Follow {
+    len: u64,
+    partial_line: bool,
+}
+
+struct View {
+    cursor: Position,
+    row_offset: usize,
+    col_offset: usize,
+}
+
+struct Split {
+    other: usize,
+    focus_left: bool,
+}
+
+// NOTE: two views of the active buffer on top of each other, the view of the
+// inactive pane is kept here
+struct HSplit {
+    view: View,
+    focus_top: bool,
+}
+
+struct Editor<'i, 'o> {
+    original_termios: Option<Termios>,
     render_x: usize,
     screen_rows: usize,
     screen_cols: usize,
     editor_cols: usize,
-    row_offset: usize,
-    col_offset: usize,
-    rows: Vec<Row>,
-    file: Option<PathBuf>,
+    tab_stop: usize,
+    soft_tabs: bool,
+    wrap_lines: bool,
+    show_tabs: bool,
+    show_tildes: bool,
+    line_numbers: bool,
+    cursor_line: bool,
+    // NOTE: shown in the middle of an empty buffer, unless it's empty
+    greeting: String,
+    col_offset_locked: bool,
     status_msg: String,
     status_time: SystemTime,
-    dirty: bool,
+    last_keypress: SystemTime,
+    // NOTE: autosave only happens while waiting for a command, not in the
+    // middle of a prompt
+    reading_command: bool,
+    autosave_failed: bool,
     quit_times: u8,
     search_dir: SearchDirection,
+    search_case_insensitive: bool,
     last_match: Option<usize>,
     win_changed: Arc<AtomicBool>,
-    stored_hl: Option<(usize, Vec<Highlight>)>,
-    syntax: Option<&'static Syntax>,
-    mark: Option<Position>,
-    clipboard: String,
+    window_size: WindowSize,
+    // NOTE: the screen lines of the last frame, see `draw_changed_rows`
+    drawn_rows: Vec<Vec<u8>>,
+    stored_hl: Vec<(usize, Vec<Highlight>)>,
+    theme: Theme,
+    shift_selection: bool,
+    read_only: bool,
+    backup: bool,
+    // NOTE: the file that keeps the cursor positions between sessions
+    positions: Option<PathBuf>,
+    kill_ring: Vec<String>,
+    kill_index: usize,
+    last_yank: Option<(Position, Position)>,
+    recenter_count: usize,
+    use_system_clipboard: bool,
+    terminal: String,
+    config: Config,
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    split: Option<Split>,
+    hsplit: Option<HSplit>,
+    last_change: Option<Change>,
+    extend_change: bool,
     stdin: Box<dyn Read + 'i>,
     stdout: Box<dyn Write + 'o>,
 }
 
 impl<'i, 'o> Editor<'i, 'o> {
-    fn new() -> Result<Editor<'i, 'o>, Box<dyn Error>> {
+    fn new(config: Config) -> Result<Editor<'i, 'o>, Box<dyn Error>> {
         let original_termios = Termios::from_fd(STDIN_FILENO)?;
         enable_raw_mode()?;
         let (rows, cols) = get_window_size()?;
+        // NOTE: pasted text is inserted as is, without auto-indent
+        io::stdout().write_all(ESC_SEQ_ENABLE_PASTE)?;
+        if config.mouse {
+            io::stdout().write_all(ESC_SEQ_ENABLE_MOUSE)?;
+        }
+
+        let mut editor = Editor::headless(config);
+        editor.original_termios = Some(original_termios);
+        editor.stdin = Box::new(io::stdin());
+        editor.stdout = Box::new(io::stdout());
+        editor.terminal = red_clipboard::terminal();
+        editor.use_system_clipboard = editor.config.system_clipboard
+            && red_clipboard::osc52_supported(&editor.terminal);
+        editor.set_window_size(rows, cols);
 
-        let win_changed = Arc::new(AtomicBool::new(false));
         signal_hook::flag::register(
             signal_hook::consts::SIGWINCH,
-            Arc::clone(&win_changed),
+            Arc::clone(&editor.win_changed),
         )?;
 
-        Ok(Editor {
-            original_termios: Some(original_termios),
-            cursor_x: 0,
-            cursor_y: 0,
+        Ok(editor)
+    }
+
+    fn headless(config: Config) -> Editor<'i, 'o> {
+        Editor {
+            original_termios: None,
             render_x: 0,
-            screen_rows: rows - RED_STATUS_HEIGHT,
-            screen_cols: cols,
-            editor_cols: cols,
-            row_offset: 0,
-            col_offset: 0,
-            rows: vec![],
-            file: None,
+            screen_rows: 0,
+            screen_cols: 0,
+            editor_cols: 0,
+            tab_stop: RED_TAB_STOP,
+            soft_tabs: config.soft_tabs,
+            wrap_lines: config.wrap_lines,
+            show_tabs: config.show_tabs,
+            show_tildes: config.show_tildes,
+            line_numbers: config.line_numbers,
+            cursor_line: config.cursor_line,
+            greeting: config.greeting.clone().unwrap_or_else(|| {
+                format!("red editor -- version {}", RED_VERSION)
+            }),
+            col_offset_locked: false,
             status_msg: String::new(),
             status_time: SystemTime::UNIX_EPOCH,
-            dirty: false,
+            last_keypress: SystemTime::UNIX_EPOCH,
+            reading_command: false,
+            autosave_failed: false,
             quit_times: RED_QUIT_TIMES,
             search_dir: SearchDirection::Forward,
+            search_case_insensitive: false,
             last_match: None,
-            win_changed,
-            stored_hl: None,
-            syntax: None,
-            mark: None,
-            clipboard: String::new(),
-            stdin: Box::new(io::stdin()),
-            stdout: Box::new(io::stdout()),
-        })
+            win_changed: Arc::new(AtomicBool::new(false)),
+            window_size: get_window_size,
+            drawn_rows: vec![],
+            stored_hl: vec![],
+            theme: THEMES[0].with_colors(&config.colors),
+            shift_selection: false,
+            read_only: false,
+            backup: false,
+            positions: None,
+            kill_ring: vec![],
+            kill_index: 0,
+            last_yank: None,
+            recenter_count: 0,
+            use_system_clipboard: false,
+            terminal: String::new(),
+            config,
+            buffers: vec![Buffer::default()],
+            current_buffer: 0,
+            split: None,
+            hsplit: None,
+            last_change: None,
+            extend_change: false,
+            stdin: Box::new(io::empty()),
+            stdout: Box::new(io::sink()),
+        }
     }
 }
 
@@ -235,6 +728,8 @@ impl<'i, 'o> Drop for Editor<'i, 'o> {
     fn drop(&mut self) {
         // NOTE: Don't panic while dropping!
         if let Some(terimos) = &self.original_termios {
+            let _ = io::stdout().write_all(ESC_SEQ_DISABLE_PASTE);
+            let _ = io::stdout().write_all(ESC_SEQ_DISABLE_MOUSE);
             if let Err(e) = termios::tcsetattr(STDIN_FILENO, TCSAFLUSH, terimos)
             {
                 eprintln!("tcsetattr error: {}", e)
@@ -287,12 +782,14 @@ fn get_window_size() -> Result<(usize, usize), Box<dyn Error>> {
     get_cursor_position()
 }
 
+const RED_SEPARATORS: &str = ",.()+-/*=~%<>[];";
+
 fn is_separator(syntax: &Syntax, c: char) -> bool {
     c.is_whitespace()
         || c == '\0'
         || syntax
             .custom_separator
-            .unwrap_or(",.()+-/*=~%<>[];")
+            .unwrap_or(RED_SEPARATORS)
             .contains(c)
 }
 
@@ -305,6 +802,26 @@ struct SyntaxState {
 }
 
 impl SyntaxState {
+    fn maybe_highlight_heading(
+        &self,
+        syntax: &Syntax,
+        highlights: &mut [Highlight],
+        render: &[char],
+    ) -> bool {
+        let level = render.iter().take_while(|&&c| c == '#').count();
+
+        if syntax.flags & HIGHLIGHT_MARKDOWN != 0
+            && !self.in_comment
+            && (1..=6).contains(&level)
+            && !matches!(render.get(level), Some(c) if !c.is_whitespace())
+        {
+            highlights.fill(Highlight::Heading);
+            true
+        } else {
+            false
+        }
+    }
+
     fn maybe_highlight_sl_comment(
         &self,
         highlights: &mut [Highlight],
@@ -428,7 +945,7 @@ impl SyntaxState {
     ) -> bool {
         let c = render[idx];
         if syntax.flags & HIGHLIGHT_NUMBERS != 0
-            && (c.is_digit(10)
+            && (c.is_ascii_digit()
                 && (self.prev_sep || prev_hl == Highlight::Number)
                 || (c == '.' && prev_hl == Highlight::Number))
         {
@@ -504,23 +1021,51 @@ impl SyntaxState {
 }
 
 impl<'i, 'o> Editor<'i, 'o> {
-    fn update_syntax(&mut self, row_idx: usize) {
+    // NOTE: A changed comment state is carried to the following rows, but
+    // only up to the end of the screen. The row after that is marked as stale
+    // and picked up once it gets drawn.
+    fn update_syntax(&mut self, mut row_idx: usize) {
+        let until =
+            (self.buffer().row_offset + self.pane_rows()).max(row_idx + 1);
+
+        while self.highlight_row(row_idx)
+            && row_idx + 1 < self.buffer().rows.len()
+        {
+            row_idx += 1;
+            if row_idx >= until {
+                self.buffer_mut().rows[row_idx].stale = true;
+                break;
+            }
+        }
+    }
+
+    fn update_stale_rows(&mut self, until: usize) {
+        for row_idx in 0..until.min(self.buffer().rows.len()) {
+            if self.buffer().rows[row_idx].stale {
+                self.update_syntax(row_idx);
+            }
+        }
+    }
+
+    fn highlight_row(&mut self, row_idx: usize) -> bool {
         let mut sstate = SyntaxState {
             prev_sep: true,
             in_string: None,
-            in_comment: row_idx > 0 && self.rows[row_idx - 1].in_comment,
+            in_comment: row_idx > 0
+                && self.buffer().rows[row_idx - 1].in_comment,
             single_line_comment: vec![],
             multi_line_comment: (vec![], vec![]),
         };
-        let num_rows = self.rows.len();
-        let row = &mut self.rows[row_idx];
+        let syntax = self.buffer().syntax;
+        let row = &mut self.buffer_mut().rows[row_idx];
+        row.stale = false;
 
         row.highlights.resize(row.render.len(), Highlight::Normal);
         row.highlights.fill(Highlight::Normal);
 
-        let syntax = match self.syntax {
+        let syntax = match syntax {
             Some(s) => s,
-            None => return,
+            None => return false,
         };
 
         sstate.single_line_comment =
@@ -533,6 +1078,16 @@ impl<'i, 'o> Editor<'i, 'o> {
         let mut iter = row.render.iter().enumerate();
 
         while let Some((idx, &c)) = iter.next() {
+            if idx == 0
+                && sstate.maybe_highlight_heading(
+                    syntax,
+                    &mut row.highlights,
+                    &row.render,
+                )
+            {
+                break;
+            }
+
             let prev_hl = row
                 .highlights
                 .get(idx.wrapping_sub(1))
@@ -598,60 +1153,139 @@ impl<'i, 'o> Editor<'i, 'o> {
 
         let in_comment_changed = row.in_comment != sstate.in_comment;
         row.in_comment = sstate.in_comment;
-        if in_comment_changed && row.index + 1 < num_rows {
-            let idx = row.index;
-            self.update_syntax(idx + 1);
-        }
+        in_comment_changed
     }
 
     fn select_syntax_highlight(&mut self) {
-        self.syntax = None;
-        let file = match &self.file {
+        self.buffer_mut().syntax = None;
+        let file = match &self.buffer().file {
             Some(f) => f,
             None => return,
         };
 
-        let file_ext = file.extension().map(OsStr::to_str).flatten();
+        let file_ext = file.extension().and_then(OsStr::to_str);
+        let file_name = file.file_name().and_then(OsStr::to_str);
 
-        self.syntax = SYNTAXES.iter().find(|syntax| {
-            syntax.extensions.iter().any(|ext| {
-                let is_ext = ext.starts_with('.');
-                is_ext && Some(&ext[1..]) == file_ext
-                    || !is_ext && file.to_string_lossy().contains(ext)
-            })
+        let configured = self.config.filetypes.iter().find(|(pattern, _)| {
+            match pattern.strip_prefix('.') {
+                Some(ext) => Some(ext) == file_ext,
+                None => file_name.is_some_and(|f| glob_match(pattern, f)),
+            }
         });
 
-        if self.syntax.is_some() {
-            for row in 0..self.rows.len() {
-                self.update_syntax(row);
+        self.buffer_mut().syntax =
+            configured.map(|(_, syntax)| *syntax).or_else(|| {
+                SYNTAXES.iter().find(|syntax| {
+                    syntax.extensions.iter().any(|ext| {
+                        let is_ext = ext.starts_with('.');
+                        is_ext && Some(&ext[1..]) == file_ext
+                            || !is_ext && file.to_string_lossy().contains(ext)
+                    })
+                })
+            });
+
+        if self.buffer().syntax.is_some() {
+            self.update_soft_tabs();
+        }
+        self.update_all_rows();
+    }
+
+    fn update_soft_tabs(&mut self) {
+        let syntax_flags =
+            self.buffer().syntax.map_or(0, |syntax| syntax.flags);
+        self.soft_tabs = self.config.soft_tabs || syntax_flags & SOFT_TABS != 0;
+    }
+
+    // NOTE: rows are highlighted once they are shown
+    fn update_all_rows(&mut self) {
+        for row in 0..self.buffer().rows.len() {
+            self.update_render(row);
+            self.buffer_mut().rows[row].stale = true;
+        }
+    }
+
+    fn tab_width(&self) -> usize {
+        self.buffer()
+            .syntax
+            .and_then(|syntax| syntax.tab_stop)
+            .unwrap_or(self.tab_stop)
+    }
+
+    fn set_filetype(&mut self) -> Result<(), Box<dyn Error>> {
+        let name = match self.prompt("Set filetype (ESC to cancel)", None)? {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        match find_syntax(name.trim()) {
+            Some(syntax) => {
+                self.buffer_mut().syntax = Some(syntax);
+                self.update_soft_tabs();
+                self.update_all_rows();
+                set_status_message!(self, "Filetype set to {}", syntax.name);
+            }
+            None => {
+                set_status_message!(self, "Unknown filetype: {}", name);
             }
         }
+
+        Ok(())
+    }
+}
+
+// NOTE: control characters are shown as ^X, which takes a single cell
+fn char_width(c: char) -> usize {
+    if c.is_ascii_control() {
+        1
+    } else {
+        c.width().unwrap_or(1).min(2)
     }
 }
 
-fn editor_row_cursor_to_render(row: &Row, cursor_x: usize) -> usize {
+fn is_combining(c: char) -> bool {
+    char_width(c) == 0
+}
+
+// NOTE: wide glyphs fill two cells of the render, the second one is a copy
+fn render_glyphs(render: &[char]) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut column = 0;
+    std::iter::from_fn(move || {
+        let &c = render.get(column)?;
+        let glyph = (column, c);
+        column += char_width(c);
+        Some(glyph)
+    })
+}
+
+fn editor_row_cursor_to_render(
+    row: &Row,
+    cursor_x: usize,
+    tab_stop: usize,
+) -> usize {
     let mut render_x = 0;
 
     for &c in row.line.iter().take(cursor_x) {
         if c == '\t' {
-            render_x += (RED_TAB_STOP - 1) - (render_x % RED_TAB_STOP);
+            render_x += (tab_stop - 1) - (render_x % tab_stop);
         }
-        render_x += 1;
+        render_x += char_width(c);
     }
 
     render_x
 }
 
-#[allow(dead_code)]
-fn editor_row_render_to_cursor(row: &Row, render_x: usize) -> usize {
+fn editor_row_render_to_cursor(
+    row: &Row,
+    render_x: usize,
+    tab_stop: usize,
+) -> usize {
     let mut current_render_x = 0;
 
     for (cursor_x, &c) in row.line.iter().enumerate() {
         if c == '\t' {
-            current_render_x +=
-                (RED_TAB_STOP - 1) - (current_render_x % RED_TAB_STOP);
+            current_render_x += (tab_stop - 1) - (current_render_x % tab_stop);
         }
-        current_render_x += 1;
+        current_render_x += char_width(c);
 
         if current_render_x > render_x {
             return cursor_x;
@@ -661,52 +1295,139 @@ fn editor_row_render_to_cursor(row: &Row, render_x: usize) -> usize {
     row.line.len()
 }
 
+impl Row {
+    fn write_glyph(
+        &self,
+        dest: &mut impl Write,
+        column: usize,
+        c: char,
+    ) -> io::Result<()> {
+        dest.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?;
+        for &(_, mark) in self.marks.iter().filter(|(col, _)| *col == column) {
+            dest.write_all(mark.encode_utf8(&mut [0; 4]).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
 impl<'i, 'o> Editor<'i, 'o> {
     fn row_append(&mut self, row: usize, content: &[char]) {
-        self.rows[row].line.extend_from_slice(content);
+        self.buffer_mut().rows[row].line.extend_from_slice(content);
         self.update_row(row);
     }
 
     fn update_row(&mut self, row_idx: usize) {
-        let row = &mut self.rows[row_idx];
+        self.update_render(row_idx);
+        self.update_syntax(row_idx);
+    }
+
+    fn update_render(&mut self, row_idx: usize) {
+        let tab_stop = self.tab_width();
+        let row = &mut self.buffer_mut().rows[row_idx];
 
         row.render.clear();
+        row.marks.clear();
         let mut idx = 0;
+        let mut glyph_start = 0;
         for &c in row.line.iter() {
+            if is_combining(c) {
+                row.marks.push((glyph_start, c));
+                continue;
+            }
+            glyph_start = idx;
             if c == '\t' {
                 row.render.push(' ');
                 idx += 1;
-                while idx % RED_TAB_STOP != 0 {
+                while idx % tab_stop != 0 {
                     row.render.push(' ');
                     idx += 1;
                 }
             } else {
-                row.render.push(c);
-                idx += 1;
+                for _ in 0..char_width(c) {
+                    row.render.push(c);
+                    idx += 1;
+                }
             }
         }
-
-        self.update_syntax(row_idx);
     }
 
     fn delete_row(&mut self, at: usize) {
-        if at < self.rows.len() {
-            self.rows.remove(at);
+        if at < self.buffer().rows.len() {
+            self.buffer_mut().rows.remove(at);
+            self.mark_stale(at);
+            self.mark_dirty();
+        }
+    }
+
+    fn trailing_blank_lines(&self) -> usize {
+        self.buffer()
+            .rows
+            .iter()
+            .rev()
+            .take_while(|row| row.line.iter().all(|c| c.is_whitespace()))
+            .count()
+    }
+
+    fn trim_trailing_blank_lines(&mut self) -> usize {
+        let count = self.trailing_blank_lines();
+        let first = self.buffer().rows.len() - count;
+
+        if count > 0 {
+            let mut text = vec![];
+            for row in &self.buffer().rows[first..] {
+                text.extend(&row.line);
+                text.push('\n');
+            }
+            self.record_edit(Edit::Delete((0, first), text));
             self.mark_dirty();
         }
+        self.buffer_mut().rows.truncate(first);
+
+        if self.buffer().cursor_y >= self.buffer().rows.len() {
+            self.buffer_mut().cursor_y = self.buffer().rows.len();
+            self.buffer_mut().cursor_x = 0;
+        }
+
+        count
+    }
+
+    fn strip_trailing_whitespace(&mut self) -> usize {
+        let mut count = 0;
+
+        for y in 0..self.buffer().rows.len() {
+            let line = &self.buffer().rows[y].line;
+            let len = line.len()
+                - line
+                    .iter()
+                    .rev()
+                    .take_while(|&&c| c == ' ' || c == '\t')
+                    .count();
+            if len == line.len() {
+                continue;
+            }
+
+            let text = line[len..].to_vec();
+            self.record_edit(Edit::Delete((len, y), text.clone()));
+            self.splice_delete((len, y), &text);
+            if y == self.buffer().cursor_y {
+                self.buffer_mut().cursor_x = self.buffer().cursor_x.min(len);
+            }
+            count += 1;
+        }
 
-        for idx in at..self.rows.len() {
-            self.rows[idx].index = idx;
+        if count > 0 {
+            self.mark_dirty();
         }
+        count
     }
 
     fn mark_dirty(&mut self) {
-        self.mark = None;
-        self.dirty = true;
+        self.buffer_mut().mark = None;
+        self.buffer_mut().dirty = true;
     }
 
     fn row_insert_char(&mut self, row_idx: usize, mut at: usize, c: char) {
-        let row = &mut self.rows[row_idx];
+        let row = &mut self.buffer_mut().rows[row_idx];
         if at > row.line.len() {
             at = row.line.len();
         }
@@ -716,7 +1437,7 @@ impl<'i, 'o> Editor<'i, 'o> {
     }
 
     fn row_delete_char(&mut self, row_idx: usize, at: usize) {
-        let row = &mut self.rows[row_idx];
+        let row = &mut self.buffer_mut().rows[row_idx];
         if at < row.line.len() {
             row.line.remove(at);
             self.update_row(row_idx);
@@ -724,67 +1445,154 @@ impl<'i, 'o> Editor<'i, 'o> {
     }
 
     fn insert_char(&mut self, c: char) {
-        if self.cursor_y == self.rows.len() {
-            self.rows.push(Row::empty(self.cursor_y))
+        if self.buffer().cursor_y == self.buffer().rows.len() {
+            self.record_edit(Edit::Insert(
+                (0, self.buffer().cursor_y),
+                vec![c, '\n'],
+            ));
+            self.buffer_mut().rows.push(Row::empty())
+        } else {
+            let at = self
+                .buffer()
+                .cursor_x
+                .min(self.buffer().rows[self.buffer().cursor_y].line.len());
+            self.record_edit(Edit::Insert(
+                (at, self.buffer().cursor_y),
+                vec![c],
+            ));
         }
 
-        self.row_insert_char(self.cursor_y, self.cursor_x, c);
+        self.row_insert_char(self.buffer().cursor_y, self.buffer().cursor_x, c);
 
-        self.cursor_x += 1;
+        self.buffer_mut().cursor_x += 1;
         self.mark_dirty();
     }
 
     fn insert_newline(&mut self) {
-        if self.cursor_x == 0 {
-            self.rows.insert(self.cursor_y, Row::empty(self.cursor_y));
-            self.update_row(self.cursor_y);
-        } else if let Some(current_row) = self.rows.get_mut(self.cursor_y) {
-            let next_line = current_row.line[self.cursor_x..].to_vec();
+        self.record_edit(Edit::Insert(
+            (self.buffer().cursor_x, self.buffer().cursor_y),
+            vec!['\n'],
+        ));
+
+        let (x, y) = (self.buffer().cursor_x, self.buffer().cursor_y);
+        if x == 0 {
+            self.buffer_mut().rows.insert(y, Row::empty());
+            self.update_row(y);
+        } else if let Some(current_row) = self.buffer_mut().rows.get_mut(y) {
+            let next_line = current_row.line[x..].to_vec();
             let next_row = Row {
-                index: self.cursor_y + 1,
                 line: next_line,
                 render: vec![],
+                marks: vec![],
                 highlights: vec![],
                 in_comment: current_row.in_comment,
+                stale: false,
             };
-            current_row.line.truncate(self.cursor_x);
-            self.rows.insert(self.cursor_y + 1, next_row);
-            self.update_row(self.cursor_y);
-            self.update_row(self.cursor_y + 1);
+            current_row.line.truncate(x);
+            self.buffer_mut().rows.insert(y + 1, next_row);
+            self.update_row(y);
+            self.update_row(y + 1);
         }
 
-        for idx in self.cursor_y + 1..self.rows.len() {
-            self.rows[idx].index = idx;
+        self.mark_dirty();
+        self.buffer_mut().cursor_y += 1;
+        self.buffer_mut().cursor_x = 0;
+    }
+
+    fn indentation(&self, row_idx: usize) -> Vec<char> {
+        self.buffer().rows.get(row_idx).map_or(vec![], |row| {
+            row.line
+                .iter()
+                .take_while(|&&c| c == ' ' || c == '\t')
+                .copied()
+                .collect()
+        })
+    }
+
+    fn new_line_indentation(&self) -> Vec<char> {
+        let mut indent = self.indentation(self.buffer().cursor_y);
+        if !self.config.auto_indent || self.buffer().cursor_x < indent.len() {
+            return vec![];
         }
 
-        self.mark_dirty();
-        self.cursor_y += 1;
-        self.cursor_x = 0;
+        // NOTE: blocks opened at the end of the line are indented once more
+        let last =
+            self.buffer()
+                .rows
+                .get(self.buffer().cursor_y)
+                .and_then(|row| {
+                    row.line[..self.buffer().cursor_x]
+                        .iter()
+                        .rev()
+                        .find(|c| !c.is_whitespace())
+                });
+        if self.buffer().syntax.is_some() && matches!(last, Some('{' | ':')) {
+            indent.extend(self.indent_unit());
+        }
+
+        indent
     }
 
     fn delete_char(&mut self) {
-        if self.cursor_x == 0 && self.cursor_y == 0 {
+        if self.buffer().cursor_x == 0 && self.buffer().cursor_y == 0 {
             return;
         }
 
-        if let Some(row) = self.rows.get_mut(self.cursor_y) {
-            if self.cursor_x > 0 {
-                self.row_delete_char(self.cursor_y, self.cursor_x - 1);
-                self.cursor_x -= 1;
+        if let Some(row) = self.buffer().rows.get(self.buffer().cursor_y) {
+            if self.buffer().cursor_x > 0 {
+                let deleted = row.line[self.buffer().cursor_x - 1];
+                let at = (self.buffer().cursor_x - 1, self.buffer().cursor_y);
+                self.record_edit(Edit::Delete(at, vec![deleted]));
+                self.row_delete_char(
+                    self.buffer().cursor_y,
+                    self.buffer().cursor_x - 1,
+                );
+                self.buffer_mut().cursor_x -= 1;
                 self.mark_dirty();
             } else {
-                let line = std::mem::take(&mut row.line);
-                let prev_row = &mut self.rows[self.cursor_y - 1];
-                self.cursor_x = prev_row.line.len();
-                self.row_append(self.cursor_y - 1, &line);
-                self.delete_row(self.cursor_y);
-                self.cursor_y -= 1;
-            }
-        } else if self.cursor_y == self.rows.len() {
+                let at = (
+                    self.buffer().rows[self.buffer().cursor_y - 1].line.len(),
+                    self.buffer().cursor_y - 1,
+                );
+                self.record_edit(Edit::Delete(at, vec!['\n']));
+                let y = self.buffer().cursor_y;
+                let line = std::mem::take(&mut self.buffer_mut().rows[y].line);
+                self.buffer_mut().cursor_x = at.0;
+                self.row_append(self.buffer().cursor_y - 1, &line);
+                self.delete_row(self.buffer().cursor_y);
+                self.buffer_mut().cursor_y -= 1;
+            }
+        } else if self.buffer().cursor_y == self.buffer().rows.len() {
             // NOTE: we are in the last empty line -> nothing to delete
-            self.cursor_y -= 1;
-            self.cursor_x = self.rows[self.cursor_y].line.len();
+            self.buffer_mut().cursor_y -= 1;
+            self.buffer_mut().cursor_x =
+                self.buffer().rows[self.buffer().cursor_y].line.len();
+        }
+    }
+
+    fn write_highlighted(
+        &mut self,
+        output: &mut impl Write,
+        color: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        self.update_stale_rows(self.buffer().rows.len());
+        for row in &self.buffer().rows {
+            let mut prev_color: Option<&Highlight> = None;
+            for (column, c) in render_glyphs(&row.render) {
+                let hl = &row.highlights[column];
+                if color && prev_color != Some(hl) {
+                    output.write_all(hl.color(&self.theme))?;
+                    prev_color = Some(hl);
+                }
+                row.write_glyph(output, column, c)?;
+            }
+            if prev_color.is_some() {
+                output.write_all(Highlight::Normal.color(&self.theme))?;
+            }
+            output.write_all(b"\n")?;
         }
+
+        Ok(())
     }
 
     fn write_rows(
@@ -792,41 +1600,110 @@ impl<'i, 'o> Editor<'i, 'o> {
         output: &mut impl Write,
     ) -> Result<usize, Box<dyn Error>> {
         let mut bytes = 0;
-        for row in &self.rows {
+        let keep_last_line_open = self.buffer().missing_final_newline
+            && !self.config.ensure_final_newline;
+        for (idx, row) in self.buffer().rows.iter().enumerate() {
             for c in &row.line {
                 bytes += output.write(format!("{}", c).as_bytes())?;
             }
-            bytes += output.write(b"\n")?;
+            if idx + 1 < self.buffer().rows.len() || !keep_last_line_open {
+                bytes += output.write(self.buffer().line_ending.as_bytes())?;
+            }
         }
 
         Ok(bytes)
     }
 
     fn save(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.file.is_none() {
+        if self.buffer().file.is_none() {
             match self.prompt("Save as (ESC to cancel)", None)? {
-                Some(file) => self.file = Some(PathBuf::from(file)),
+                Some(file) => {
+                    self.buffer_mut().file = Some(PathBuf::from(file))
+                }
                 None => {
                     set_status_message!(self, "Save aborted");
                     return Ok(());
                 }
             }
         }
-        if self.syntax.is_none() {
+        if self.buffer().invalid_utf8 {
+            set_status_message!(
+                self,
+                "Invalid UTF-8 was replaced. Save anyway? (y/n)"
+            );
+            self.refresh_screen()?;
+            if self.read_key()? != EditorKey::Other('y') {
+                set_status_message!(self, "Save aborted");
+                return Ok(());
+            }
+            self.buffer_mut().invalid_utf8 = false;
+        }
+        // NOTE: don't clobber changes that were made by someone else
+        if self.changed_on_disk() {
+            set_status_message!(self, "File changed on disk, overwrite? (y/n)");
+            self.refresh_screen()?;
+            if self.read_key()? != EditorKey::Other('y') {
+                set_status_message!(self, "Save aborted");
+                return Ok(());
+            }
+        }
+        if self.buffer().syntax.is_none() {
             self.select_syntax_highlight();
         }
 
-        self.dirty = false;
+        self.write_file(self.config.strip_trailing_whitespace)
+    }
+
+    // NOTE: the buffer stays dirty when the file can't be written
+    fn write_file(&mut self, strip: bool) -> Result<(), Box<dyn Error>> {
+        let stripped = match strip {
+            true => self.strip_trailing_whitespace(),
+            false => 0,
+        };
+
         let mut write_to_file = || -> Result<(), Box<dyn Error>> {
-            match &self.file {
+            match self.buffer().file.clone() {
                 Some(path) => {
-                    let mut file = BufWriter::new(File::create(path)?);
+                    // NOTE: only the content from before this session is
+                    // kept, so there is one backup per file
+                    let backup = match self.backup && !self.buffer().made_backup
+                    {
+                        true => {
+                            let backup = backup_file(&path);
+                            self.buffer_mut().made_backup = backup.is_ok();
+                            backup
+                        }
+                        false => Ok(false),
+                    };
+                    let backup = match backup {
+                        Ok(true) => ", backup written".to_string(),
+                        Ok(false) => String::new(),
+                        Err(err) => format!(", backup failed: {}", err),
+                    };
+                    let mut file = BufWriter::new(File::create(&path)?);
                     let bytes_written = self.write_rows(&mut file)?;
+                    file.flush()?;
+                    self.buffer_mut().disk_mtime = file_mtime(&path);
+                    let converted = match self.buffer().line_ending {
+                        _ if !self.buffer().mixed_line_endings => "",
+                        LineEnding::Lf => " (mixed endings -> LF)",
+                        LineEnding::CrLf => " (mixed endings -> CRLF)",
+                    };
+                    let stripped = match stripped {
+                        0 => String::new(),
+                        1 => ", stripped 1 line".to_string(),
+                        n => format!(", stripped {} lines", n),
+                    };
                     set_status_message!(
                         self,
-                        "{} bytes written to disk",
-                        bytes_written
+                        "{} bytes written to disk{}{}{}",
+                        bytes_written,
+                        converted,
+                        stripped,
+                        backup
                     );
+                    self.buffer_mut().mixed_line_endings = false;
+                    self.remember_positions();
 
                     Ok(())
                 }
@@ -835,7 +1712,11 @@ impl<'i, 'o> Editor<'i, 'o> {
         };
 
         match write_to_file() {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                self.buffer_mut().dirty = false;
+                self.buffer_mut().history.mark_saved();
+                Ok(())
+            }
             Err(msg) => {
                 set_status_message!(self, "Can't save! I/O error: {}", msg);
                 Ok(())
@@ -844,20 +1725,211 @@ impl<'i, 'o> Editor<'i, 'o> {
     }
 }
 
-fn editor_find_callback(editor: &mut Editor, needle: &[char], key: EditorKey) {
-    if needle.is_empty() {
-        return;
+impl<'i, 'o> Editor<'i, 'o> {
+    fn buffer(&self) -> &Buffer {
+        &self.buffers[self.current_buffer]
     }
 
-    if let Some((idx, highlight)) = &editor.stored_hl {
-        editor.rows[*idx].highlights = highlight.clone();
-        editor.stored_hl = None;
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current_buffer]
     }
 
-    match key {
-        EditorKey::Ctrl('m') | EditorKey::Other(ESC) => {
-            editor.last_match = None;
-            editor.search_dir = SearchDirection::Forward;
+    fn switch_buffer(&mut self, idx: usize) {
+        self.current_buffer = idx;
+        // NOTE: the yanked range and the stored search highlights belong to
+        // the previous buffer
+        self.last_yank = None;
+        self.stored_hl.clear();
+        // NOTE: both views of a horizontal split show the active buffer
+        self.hsplit = None;
+    }
+
+    fn open_buffer(&mut self, file_path: &Path) -> Result<(), Box<dyn Error>> {
+        self.buffers.push(Buffer::default());
+        self.switch_buffer(self.buffers.len() - 1);
+        self.open(file_path)
+    }
+
+    fn find_file(&mut self) -> Result<(), Box<dyn Error>> {
+        let file = match self.prompt("Open file (ESC to cancel)", None)? {
+            Some(file) => PathBuf::from(file),
+            None => return Ok(()),
+        };
+
+        if self.buffer().file.as_ref() == Some(&file) {
+            return Ok(());
+        }
+        let open = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.file.as_ref() == Some(&file));
+        match open {
+            Some(idx)
+                if self.split.as_ref().is_some_and(|s| s.other == idx) =>
+            {
+                self.focus_other_pane()
+            }
+            Some(idx) => self.switch_buffer(idx),
+            None => {
+                let previous = self.current_buffer;
+                if let Err(err) = self.open_buffer(&file) {
+                    self.switch_buffer(previous);
+                    self.buffers.pop();
+                    set_status_message!(self, "Can't open file: {}", err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // NOTE: the buffer in the other window is skipped
+    fn next_buffer(&mut self) {
+        let other = self.split.as_ref().map(|split| split.other);
+        let count = self.buffers.len();
+        let next = (1..count)
+            .map(|offset| (self.current_buffer + offset) % count)
+            .find(|&idx| Some(idx) != other);
+
+        match next {
+            Some(idx) => self.switch_buffer(idx),
+            None => {
+                set_status_message!(self, "There is no other buffer");
+            }
+        }
+    }
+
+    fn is_any_buffer_dirty(&self) -> bool {
+        self.buffers.iter().any(|buffer| buffer.dirty)
+    }
+
+    fn split_window(&mut self, other: usize) {
+        if other != self.current_buffer && other < self.buffers.len() {
+            self.split = Some(Split {
+                other,
+                focus_left: true,
+            });
+        }
+    }
+
+    // NOTE: only changes which pane is active, the buffers stay as they are
+    fn swap_panes(&mut self) {
+        if let Some(split) = &mut self.split {
+            std::mem::swap(&mut split.other, &mut self.current_buffer);
+            split.focus_left = !split.focus_left;
+        }
+    }
+
+    fn focus_other_pane(&mut self) {
+        if self.split.is_some() {
+            self.swap_panes();
+            self.switch_buffer(self.current_buffer);
+        }
+    }
+
+    fn pane_cols(&self) -> usize {
+        match &self.split {
+            Some(split) if split.focus_left => self.screen_cols / 2,
+            Some(_) => self.screen_cols.saturating_sub(1) / 2,
+            None => self.screen_cols,
+        }
+    }
+
+    fn split_view(&mut self) {
+        if self.split.is_some() || self.hsplit.is_some() {
+            set_status_message!(self, "There is already another window");
+            return;
+        }
+        self.hsplit = Some(HSplit {
+            view: self.view(),
+            focus_top: true,
+        });
+    }
+
+    fn close_other_window(&mut self) {
+        if self.split.is_none() && self.hsplit.is_none() {
+            set_status_message!(self, "There is no other window");
+        }
+        self.split = None;
+        self.hsplit = None;
+    }
+
+    fn focus_other_view(&mut self) {
+        if let Some(hsplit) = self.hsplit.take() {
+            self.hsplit = Some(HSplit {
+                view: self.view(),
+                focus_top: !hsplit.focus_top,
+            });
+            self.set_view(hsplit.view);
+            // NOTE: the other pane may have removed lines in the meantime
+            self.buffer_mut().cursor_y =
+                self.buffer().cursor_y.min(self.buffer().rows.len());
+            let len = self
+                .buffer()
+                .rows
+                .get(self.buffer().cursor_y)
+                .map_or(0, |r| r.line.len());
+            self.buffer_mut().cursor_x = self.buffer().cursor_x.min(len);
+        }
+    }
+
+    fn pane_rows(&self) -> usize {
+        match &self.hsplit {
+            Some(hsplit) if hsplit.focus_top => self.screen_rows / 2,
+            Some(_) => self.screen_rows.saturating_sub(1) / 2,
+            None => self.screen_rows,
+        }
+    }
+
+    fn pane_top(&self) -> usize {
+        match &self.hsplit {
+            Some(hsplit) if !hsplit.focus_top => self.screen_rows / 2 + 1,
+            _ => 0,
+        }
+    }
+
+    fn pane_left(&self) -> usize {
+        match &self.split {
+            Some(split) if !split.focus_left => self.screen_cols / 2 + 1,
+            _ => 0,
+        }
+    }
+}
+
+fn editor_goto_callback(editor: &mut Editor, input: &[char], _: EditorKey) {
+    let line = input.iter().collect::<String>().parse::<usize>();
+    if let Ok(line @ 1..) = line {
+        editor.buffer_mut().cursor_y =
+            line.min(editor.buffer().rows.len()).saturating_sub(1);
+        editor.buffer_mut().cursor_x = 0;
+    }
+}
+
+fn chars_match(hay: &[char], needle: &[char], case_insensitive: bool) -> bool {
+    if case_insensitive {
+        hay.iter()
+            .zip(needle)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+    } else {
+        hay == needle
+    }
+}
+
+fn editor_find_callback(editor: &mut Editor, needle: &[char], key: EditorKey) {
+    if key == EditorKey::Ctrl('i') {
+        editor.search_case_insensitive = !editor.search_case_insensitive;
+    }
+
+    if needle.is_empty() {
+        return;
+    }
+
+    editor.restore_highlight();
+
+    match key {
+        EditorKey::Ctrl('m') | EditorKey::Other(ESC) => {
+            editor.last_match = None;
+            editor.search_dir = SearchDirection::Forward;
             return;
         }
         EditorKey::ArrowRight | EditorKey::ArrowDown | EditorKey::Ctrl('f') => {
@@ -876,95 +1948,573 @@ fn editor_find_callback(editor: &mut Editor, needle: &[char], key: EditorKey) {
         editor.search_dir = SearchDirection::Forward;
     }
 
-    let mut search_idx = editor.last_match.unwrap_or(editor.rows.len());
+    let mut search_idx =
+        editor.last_match.unwrap_or(editor.buffer().rows.len());
 
-    for _ in 0..editor.rows.len() {
-        search_idx = editor.search_dir.step(search_idx, editor.rows.len() - 1);
+    for _ in 0..editor.buffer().rows.len() {
+        search_idx = editor
+            .search_dir
+            .step(search_idx, editor.buffer().rows.len() - 1);
 
-        let row = editor
-            .rows
-            .get_mut(search_idx)
-            .expect("search index should always be valid!");
+        let len = match editor.buffer().rows.get(search_idx) {
+            Some(row) => row.line.len(),
+            None => break,
+        };
 
-        if let Some(idx) =
-            row.line.windows(needle.len()).position(|hay| hay == needle)
-        {
+        if let Some((idx, end)) = (0..=len).find_map(|x| {
+            editor
+                .match_end((x, search_idx), needle)
+                .map(|end| (x, end))
+        }) {
             editor.last_match = Some(search_idx);
-            editor.cursor_y = search_idx;
-            editor.cursor_x = idx;
-            let half_screen = editor.screen_rows / 2;
+            editor.buffer_mut().cursor_y = search_idx;
+            editor.buffer_mut().cursor_x = idx;
+            let half_screen = editor.pane_rows() / 2;
             if half_screen < search_idx {
-                editor.row_offset = search_idx - half_screen;
+                editor.buffer_mut().row_offset = search_idx - half_screen;
             }
 
-            editor.stored_hl = Some((search_idx, row.highlights.clone()));
-            let render_idx = editor_row_cursor_to_render(row, idx);
-            row.highlights[render_idx..render_idx + needle.len()]
-                .fill(Highlight::Match);
+            editor.highlight_match((idx, search_idx), end);
             break;
         }
     }
 }
 
 impl<'i, 'o> Editor<'i, 'o> {
+    // NOTE: a needle with newlines has to match the end of its first line,
+    // whole lines in between and the start of its last line
+    fn match_end(&self, (x, y): Position, needle: &[char]) -> Option<Position> {
+        let parts = needle.split(|&c| c == '\n').collect::<Vec<_>>();
+        let mut end = (x, y);
+
+        for (idx, part) in parts.iter().enumerate() {
+            let line = &self.buffer().rows.get(y + idx)?.line;
+            let begin = if idx == 0 { x } else { 0 };
+            let hay = line.get(begin..begin + part.len())?;
+            if !chars_match(hay, part, self.search_case_insensitive)
+                || (idx + 1 < parts.len() && begin + part.len() != line.len())
+            {
+                return None;
+            }
+            end = (begin + part.len(), y + idx);
+        }
+
+        Some(end)
+    }
+
+    fn highlight_match(&mut self, (x, y): Position, end: Position) {
+        self.update_stale_rows(end.1 + 1);
+        let tab_stop = self.tab_width();
+        for idx in y..=end.1 {
+            let highlights = self.buffer().rows[idx].highlights.clone();
+            self.stored_hl.push((idx, highlights));
+            let row = &mut self.buffer_mut().rows[idx];
+            let begin = match idx == y {
+                true => editor_row_cursor_to_render(row, x, tab_stop),
+                false => 0,
+            };
+            let stop = match idx == end.1 {
+                true => editor_row_cursor_to_render(row, end.0, tab_stop),
+                false => row.render.len(),
+            };
+            row.highlights[begin..stop].fill(Highlight::Match);
+        }
+    }
+
+    fn restore_highlight(&mut self) {
+        for (idx, highlight) in std::mem::take(&mut self.stored_hl) {
+            if let Some(row) = self.buffer_mut().rows.get_mut(idx) {
+                row.highlights = highlight;
+            }
+        }
+    }
+
+    fn view(&self) -> View {
+        View {
+            cursor: (self.buffer().cursor_x, self.buffer().cursor_y),
+            row_offset: self.buffer().row_offset,
+            col_offset: self.buffer().col_offset,
+        }
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.buffer_mut().cursor_x = view.cursor.0;
+        self.buffer_mut().cursor_y = view.cursor.1;
+        self.buffer_mut().row_offset = view.row_offset;
+        self.buffer_mut().col_offset = view.col_offset;
+    }
+
+    // NOTE: lines past the end jump to the last line
+    fn goto_position(&mut self, (line, col): (usize, usize)) {
+        self.buffer_mut().cursor_y =
+            line.min(self.buffer().rows.len()).saturating_sub(1);
+        self.buffer_mut().cursor_x = self
+            .buffer()
+            .rows
+            .get(self.buffer().cursor_y)
+            .map_or(0, |row| col.saturating_sub(1).min(row.line.len()));
+    }
+
+    fn goto_line(&mut self) -> Result<(), Box<dyn Error>> {
+        let view = self.view();
+
+        let input = match self
+            .prompt("Go to line (ESC to cancel)", Some(editor_goto_callback))?
+        {
+            Some(input) => input,
+            None => {
+                self.set_view(view);
+                return Ok(());
+            }
+        };
+
+        match input.parse::<usize>() {
+            Ok(line @ 1..) => self.goto_position((line, 1)),
+            _ => {
+                self.set_view(view);
+                set_status_message!(self, "`{}` isn't a line number", input);
+            }
+        }
+
+        Ok(())
+    }
+
     fn find(&mut self) -> Result<(), Box<dyn Error>> {
-        let saved_cx = self.cursor_x;
-        let saved_cy = self.cursor_y;
-        let saved_coloff = self.col_offset;
-        let saved_rowoff = self.row_offset;
+        let view = self.view();
 
-        let input = self
-            .prompt("Search (ESC/Arrows/Enter)", Some(editor_find_callback))?;
+        let input = self.prompt_lines(
+            "Search (ESC/Arrows/Enter/Tab/C-j)",
+            Some(editor_find_callback),
+            true,
+        )?;
+        self.search_case_insensitive = false;
         if input.is_none() {
-            self.cursor_x = saved_cx;
-            self.cursor_y = saved_cy;
-            self.col_offset = saved_coloff;
-            self.row_offset = saved_rowoff;
+            self.set_view(view);
+        }
+
+        Ok(())
+    }
+
+    fn find_next(&self, needle: &[char], (x, y): Position) -> Option<Position> {
+        self.buffer()
+            .rows
+            .iter()
+            .enumerate()
+            .skip(y)
+            .find_map(|(idx, row)| {
+                let begin = if idx == y { x.min(row.line.len()) } else { 0 };
+                row.line[begin..]
+                    .windows(needle.len())
+                    .position(|hay| {
+                        chars_match(hay, needle, self.search_case_insensitive)
+                    })
+                    .map(|pos| (begin + pos, idx))
+            })
+    }
+
+    fn replace_at(&mut self, (x, y): Position, len: usize, with: &[char]) {
+        let old = self.buffer().rows[y].line[x..x + len].to_vec();
+        self.record_edit(Edit::Delete((x, y), old));
+        for _ in 0..len {
+            self.row_delete_char(y, x);
+        }
+
+        if !with.is_empty() {
+            self.record_edit(Edit::Insert((x, y), with.to_vec()));
+        }
+        for (idx, &c) in with.iter().enumerate() {
+            self.row_insert_char(y, x + idx, c);
+        }
+
+        self.mark_dirty();
+    }
+
+    fn ask_replace(
+        &mut self,
+        (x, y): Position,
+        len: usize,
+    ) -> Result<char, Box<dyn Error>> {
+        self.buffer_mut().cursor_x = x;
+        self.buffer_mut().cursor_y = y;
+        self.highlight_match((x, y), (x + len, y));
+        set_status_message!(self, "Replace this match? (y/n/a/ESC)");
+        self.refresh_screen()?;
+
+        let answer = loop {
+            match self.read_key()? {
+                EditorKey::Other(c @ ('y' | 'n' | 'a')) => break c,
+                EditorKey::Other(ESC | 'q') | EditorKey::Ctrl('g') => {
+                    break 'q'
+                }
+                _ => (),
+            }
+        };
+
+        self.restore_highlight();
+        Ok(answer)
+    }
+
+    fn replace(&mut self) -> Result<(), Box<dyn Error>> {
+        let view = self.view();
+
+        let needle = match self.prompt(
+            "Replace (ESC/Arrows/Enter/Tab)",
+            Some(editor_find_callback),
+        )? {
+            Some(needle) => needle,
+            None => {
+                self.search_case_insensitive = false;
+                self.set_view(view);
+                return Ok(());
+            }
+        };
+        let with = match self.prompt(
+            &format!("Replace `{}` with (ESC to cancel)", needle),
+            None,
+        )? {
+            Some(with) => with.chars().collect::<Vec<_>>(),
+            None => {
+                self.search_case_insensitive = false;
+                set_status_message!(self, "Replace aborted");
+                return Ok(());
+            }
+        };
+        let needle = needle.chars().collect::<Vec<_>>();
+
+        let mut start = (self.buffer().cursor_x, self.buffer().cursor_y);
+        let mut pos = start;
+        let mut wrapped = false;
+        let mut replace_all = false;
+        let mut count = 0;
+
+        loop {
+            let found = match self.find_next(&needle, pos) {
+                Some((x, y)) if wrapped && (y, x) >= (start.1, start.0) => {
+                    break
+                }
+                Some(found) => found,
+                None if !wrapped => {
+                    wrapped = true;
+                    pos = (0, 0);
+                    continue;
+                }
+                None => break,
+            };
+
+            let answer = if replace_all {
+                'y'
+            } else {
+                self.ask_replace(found, needle.len())?
+            };
+
+            match answer {
+                'y' | 'a' => {
+                    self.replace_at(found, needle.len(), &with);
+                    count += 1;
+                    replace_all |= answer == 'a';
+                    // NOTE: keep the stop position in place when the line
+                    // before it changes length
+                    if wrapped && found.1 == start.1 {
+                        start.0 =
+                            (start.0 + with.len()).saturating_sub(needle.len());
+                    }
+                    pos = (found.0 + with.len(), found.1);
+                }
+                'n' => pos = (found.0 + needle.len(), found.1),
+                _ => break,
+            }
+            self.buffer_mut().cursor_x = pos.0;
+            self.buffer_mut().cursor_y = pos.1;
         }
 
+        self.search_case_insensitive = false;
+        set_status_message!(self, "Replaced {} occurrences", count);
         Ok(())
     }
 
     fn open(&mut self, file_path: &Path) -> Result<(), Box<dyn Error>> {
-        let reader = match File::open(file_path) {
+        self.buffer_mut().history.clear();
+
+        self.buffer_mut().disk_mtime = file_mtime(file_path);
+        let mut reader = match File::open(file_path) {
             Ok(file) => BufReader::new(file),
             Err(err) if err.kind() == io::ErrorKind::NotFound => {
-                self.file = Some(file_path.to_owned());
+                self.buffer_mut().file = Some(file_path.to_owned());
                 self.select_syntax_highlight();
                 return Ok(());
             }
             Err(err) => return Err(Box::new(err)),
         };
 
-        for (index, line) in reader.lines().enumerate() {
-            let line = line?
-                .trim_end_matches(|c| c == '\n' || c == '\r')
-                .chars()
-                .collect();
-            let row = Row {
-                index,
-                line,
-                render: vec![],
-                highlights: vec![],
-                in_comment: false,
-            };
-            self.rows.push(row);
-            self.update_row(self.rows.len() - 1);
+        let (mut lf, mut crlf) = (0, 0);
+        let mut bytes = vec![];
+        self.buffer_mut().invalid_utf8 = false;
+        self.buffer_mut().missing_final_newline = false;
+        while reader.read_until(b'\n', &mut bytes)? > 0 {
+            self.buffer_mut().missing_final_newline = !bytes.ends_with(b"\n");
+            // NOTE: invalid bytes are replaced, see `save`
+            let line = String::from_utf8_lossy(&bytes);
+            self.buffer_mut().invalid_utf8 |= matches!(line, Cow::Owned(_));
+
+            if line.ends_with("\r\n") {
+                crlf += 1;
+            } else if line.ends_with('\n') {
+                lf += 1;
+            }
+            let trimmed = line.trim_end_matches(['\n', '\r']);
+            // NOTE: `select_syntax_highlight` renders the rows below
+            self.buffer_mut().rows.push(Row {
+                line: trimmed.chars().collect(),
+                ..Row::empty()
+            });
+            bytes.clear();
         }
 
-        self.file = Some(file_path.to_owned());
+        self.buffer_mut().line_ending = if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+        self.buffer_mut().mixed_line_endings = lf > 0 && crlf > 0;
+        self.buffer_mut().file = Some(file_path.to_owned());
         self.select_syntax_highlight();
 
+        let saved = self.positions.as_ref().and_then(|positions| {
+            red_positions::lookup(positions, &fs::canonicalize(file_path).ok()?)
+        });
+        if let Some((x, y)) = saved {
+            self.goto_position((y + 1, x + 1));
+        }
+
+        Ok(())
+    }
+
+    // NOTE: failing to remember a position isn't worth an error message
+    fn remember_positions(&self) {
+        let positions = match &self.positions {
+            Some(positions) => positions,
+            None => return,
+        };
+
+        for buffer in &self.buffers {
+            if let Some(file) =
+                buffer.file.as_ref().and_then(|f| fs::canonicalize(f).ok())
+            {
+                let position = (buffer.cursor_x, buffer.cursor_y);
+                red_positions::store(positions, &file, position).ok();
+            }
+        }
+    }
+
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        let file = match self.buffer().file.clone() {
+            Some(file) => file,
+            None => {
+                set_status_message!(self, "There is no file to reload");
+                return Ok(());
+            }
+        };
+
+        if self.buffer().dirty {
+            set_status_message!(
+                self,
+                "File has unsaved changes. Reload anyway? (y/n)"
+            );
+            self.refresh_screen()?;
+            if self.read_key()? != EditorKey::Other('y') {
+                set_status_message!(self, "Reload aborted");
+                return Ok(());
+            }
+        }
+
+        let (x, y) = (self.buffer().cursor_x, self.buffer().cursor_y);
+        self.buffer_mut().rows.clear();
+        self.buffer_mut().mark = None;
+        self.stored_hl.clear();
+        self.open(&file)?;
+        self.buffer_mut().dirty = false;
+        self.goto_position((y + 1, x + 1));
+        if self.buffer().follow.is_some() {
+            self.start_follow()?;
+        }
+
+        set_status_message!(
+            self,
+            "Reloaded {} lines",
+            self.buffer().rows.len()
+        );
+        Ok(())
+    }
+
+    fn load_text(&mut self, text: &str) {
+        for line in text.lines() {
+            self.append_row(line.chars().collect());
+        }
+        self.buffer_mut().dirty = true;
+    }
+
+    fn append_row(&mut self, line: Vec<char>) {
+        self.buffer_mut().rows.push(Row {
+            line,
+            render: vec![],
+            marks: vec![],
+            highlights: vec![],
+            in_comment: false,
+            stale: false,
+        });
+        self.update_row(self.buffer().rows.len() - 1);
+    }
+
+    fn start_follow(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut file = match self.buffer().file.as_ref().map(File::open) {
+            Some(Ok(file)) => file,
+            // NOTE: the file may still be created, see `maybe_follow`
+            Some(Err(err)) if err.kind() == io::ErrorKind::NotFound => {
+                self.buffer_mut().follow = Some(Follow {
+                    len: 0,
+                    partial_line: false,
+                });
+                return Ok(());
+            }
+            Some(Err(err)) => return Err(Box::new(err)),
+            None => return Ok(()),
+        };
+
+        let len = file.metadata()?.len();
+        let mut partial_line = false;
+        if len > 0 {
+            let mut last = [0; 1];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            partial_line = last[0] != b'\n';
+        }
+
+        self.buffer_mut().follow = Some(Follow { len, partial_line });
+        self.buffer_mut().cursor_y = self.buffer().rows.len().saturating_sub(1);
+        self.buffer_mut().cursor_x = 0;
+
         Ok(())
     }
 
+    fn maybe_follow(&mut self) -> Result<bool, Box<dyn Error>> {
+        let (path, follow) = match (&self.buffer().file, &self.buffer().follow)
+        {
+            (Some(path), Some(follow)) => (path.clone(), follow),
+            _ => return Ok(false),
+        };
+
+        let len = match fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(false)
+            }
+            Err(err) => return Err(Box::new(err)),
+        };
+
+        let at_bottom = self.buffer().cursor_y + 1 >= self.buffer().rows.len();
+
+        if len < follow.len {
+            // NOTE: The file was truncated, start over.
+            self.buffer_mut().rows.clear();
+            self.open(&path)?;
+        } else if len > follow.len {
+            let mut file = File::open(&path)?;
+            let mut appended = vec![];
+            file.seek(SeekFrom::Start(follow.len))?;
+            file.read_to_end(&mut appended)?;
+
+            let appended = String::from_utf8_lossy(&appended);
+            let mut lines = appended.split('\n');
+            if follow.partial_line {
+                if let (Some(first), Some(last)) =
+                    (lines.next(), self.buffer().rows.len().checked_sub(1))
+                {
+                    let first = first.trim_end_matches('\r').chars();
+                    self.row_append(last, &first.collect::<Vec<_>>());
+                }
+            }
+            for line in lines {
+                self.append_row(line.trim_end_matches('\r').chars().collect());
+            }
+            // NOTE: A trailing newline produces an empty last piece, it's
+            // part of the last line and not a line of its own.
+            if appended.ends_with('\n') {
+                self.buffer_mut().rows.pop();
+            }
+        } else {
+            return Ok(false);
+        }
+
+        let cursor = (self.buffer().cursor_x, self.buffer().cursor_y);
+        self.start_follow()?;
+        if !at_bottom {
+            self.buffer_mut().cursor_x = cursor.0;
+            self.buffer_mut().cursor_y = cursor.1.min(self.buffer().rows.len());
+        }
+
+        Ok(true)
+    }
+
+    // NOTE: clearing the flag first keeps a resize that arrives while
+    // redrawing for the next call
+    fn update_window_size(&mut self) -> Result<bool, Box<dyn Error>> {
+        if !self.win_changed.swap(false, atomic::Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let (rows, cols) = (self.window_size)()?;
+        self.set_window_size(rows, cols);
+        Ok(true)
+    }
+
+    // NOTE: a file that was replaced by an older copy has changed as well
+    fn changed_on_disk(&self) -> bool {
+        let mtime = self.buffer().file.as_deref().and_then(file_mtime);
+        mtime.is_some() && mtime != self.buffer().disk_mtime
+    }
+
+    // NOTE: autosave never asks anything, files that would need an answer
+    // are left for an explicit save
+    fn maybe_autosave(&mut self) -> Result<bool, Box<dyn Error>> {
+        let secs = match self.config.autosave_secs {
+            Some(secs) => secs,
+            None => return Ok(false),
+        };
+        // NOTE: a failed autosave is retried after the next keypress
+        if self.buffer().file.is_none()
+            || !self.buffer().dirty
+            || self.read_only
+            || self.buffer().follow.is_some()
+            || !self.reading_command
+            || self.autosave_failed
+        {
+            return Ok(false);
+        }
+        let idle = SystemTime::now()
+            .duration_since(self.last_keypress)
+            .is_ok_and(|idle| idle.as_secs() >= secs);
+        if !idle || self.changed_on_disk() || self.buffer().invalid_utf8 {
+            return Ok(false);
+        }
+
+        // NOTE: whitespace at the cursor may be about to get more text
+        self.write_file(false)?;
+        self.autosave_failed = self.buffer().dirty;
+        set_status_message!(self, "Autosave: {}", self.status_msg);
+        Ok(true)
+    }
+
     fn maybe_update_screen(&mut self) -> Result<(), Box<dyn Error>> {
         if self.win_changed.load(atomic::Ordering::Relaxed) {
-            let (rows, cols) = get_window_size()?;
-            self.screen_rows = rows - RED_STATUS_HEIGHT;
-            self.screen_cols = cols;
             self.refresh_screen()?;
-            self.win_changed.store(false, atomic::Ordering::Relaxed);
+        }
+
+        if self.maybe_follow()? {
+            self.refresh_screen()?;
+        }
+
+        if self.maybe_autosave()? {
+            self.refresh_screen()?;
         }
 
         Ok(())
@@ -997,6 +2547,8 @@ impl<'i, 'o> Editor<'i, 'o> {
                 b"[D" => Ok(EditorKey::ArrowLeft),
                 b"[H" | b"OH" => Ok(EditorKey::Home),
                 b"[F" | b"OF" => Ok(EditorKey::End),
+                b"[Z" => Ok(EditorKey::BackTab),
+                b"[<" => self.read_mouse(),
                 esc_seq
                     if esc_seq[0] == b'[' && esc_seq[1].is_ascii_digit() =>
                 {
@@ -1004,9 +2556,43 @@ impl<'i, 'o> Editor<'i, 'o> {
                         return Ok(EditorKey::Other(ESC));
                     }
 
-                    match &seq {
-                        b"[1~" | b"[7~" => Ok(EditorKey::Home),
-                        b"[3~" => Ok(EditorKey::Delete),
+                    if &seq == b"[20" {
+                        let mut end = [0; 2];
+                        self.stdin.read_exact(&mut end)?;
+                        return match &end {
+                            b"0~" => Ok(EditorKey::PasteStart),
+                            b"1~" => Ok(EditorKey::PasteEnd),
+                            _ => Ok(EditorKey::Other(ESC)),
+                        };
+                    }
+
+                    // NOTE: arrows with modifiers, Alt+Up/Down move the
+                    // line like M-p and M-n, Ctrl+Left/Right move by words
+                    if &seq == b"[1;" {
+                        let mut modified = [0; 2];
+                        self.stdin.read_exact(&mut modified)?;
+                        return match &modified {
+                            b"3A" => Ok(EditorKey::Meta('p')),
+                            b"3B" => Ok(EditorKey::Meta('n')),
+                            b"2A" => Ok(EditorKey::ShiftArrowUp),
+                            b"2B" => Ok(EditorKey::ShiftArrowDown),
+                            b"2C" => Ok(EditorKey::ShiftArrowRight),
+                            b"2D" => Ok(EditorKey::ShiftArrowLeft),
+                            b"5C" => Ok(EditorKey::Meta('f')),
+                            b"5D" => Ok(EditorKey::Meta('b')),
+                            [_, b'A'] => Ok(EditorKey::ArrowUp),
+                            [_, b'B'] => Ok(EditorKey::ArrowDown),
+                            [_, b'C'] => Ok(EditorKey::ArrowRight),
+                            [_, b'D'] => Ok(EditorKey::ArrowLeft),
+                            [_, b'H'] => Ok(EditorKey::Home),
+                            [_, b'F'] => Ok(EditorKey::End),
+                            _ => Ok(EditorKey::Other(ESC)),
+                        };
+                    }
+
+                    match &seq {
+                        b"[1~" | b"[7~" => Ok(EditorKey::Home),
+                        b"[3~" => Ok(EditorKey::Delete),
                         b"[4~" | b"[8~" => Ok(EditorKey::End),
                         b"[5~" => Ok(EditorKey::PageUp),
                         b"[6~" => Ok(EditorKey::PageDown),
@@ -1019,6 +2605,7 @@ impl<'i, 'o> Editor<'i, 'o> {
             let key = parse_utf8(cbyte[0], &mut self.stdin)?;
             match key {
                 '\0' => Ok(EditorKey::Ctrl(' ')),
+                '\x1f' => Ok(EditorKey::Ctrl('/')),
                 '\x01'..='\x1a' => {
                     Ok(EditorKey::Ctrl((key as u8 + 0x60) as char))
                 }
@@ -1027,142 +2614,1087 @@ impl<'i, 'o> Editor<'i, 'o> {
         }
     }
 
-    fn prompt(
-        &mut self,
-        prompt: &str,
-        callback: Option<fn(&mut Editor, &[char], EditorKey)>,
-    ) -> Result<Option<String>, Box<dyn Error>> {
-        let mut str_input = String::new();
-        let mut vec_input = vec![];
-        let callback = match callback {
-            Some(f) => f,
-            None => |_: &mut Editor, _: &[char], _: EditorKey| {},
+    // NOTE: SGR mouse reports look like ESC[<button;x;yM (m on release)
+    fn read_mouse(&mut self) -> Result<EditorKey, Box<dyn Error>> {
+        let mut report = vec![];
+        let mut byte = [0; 1];
+        loop {
+            self.stdin.read_exact(&mut byte)?;
+            match byte[0] {
+                b'M' | b'm' => break,
+                b'0'..=b'9' | b';' if report.len() < 32 => report.push(byte[0]),
+                _ => return Ok(EditorKey::Other(ESC)),
+            }
+        }
+
+        let fields = String::from_utf8_lossy(&report)
+            .split(';')
+            .map(str::parse::<usize>)
+            .collect::<Result<Vec<_>, _>>();
+        let (button, x, y) = match fields.as_deref() {
+            Ok(&[button, x, y]) if x > 0 && y > 0 => (button, x, y),
+            _ => return Ok(EditorKey::Other(ESC)),
+        };
+
+        // NOTE: shift, meta and control don't change what a button does
+        let button = button & !(4 | 8 | 16);
+        match (button, byte[0]) {
+            (64, b'M') => return Ok(EditorKey::WheelUp),
+            (65, b'M') => return Ok(EditorKey::WheelDown),
+            _ => (),
+        }
+
+        // NOTE: only the left button is used, 32 marks motion events
+        if button & !32 != 0 {
+            return Ok(EditorKey::Other(ESC));
+        }
+
+        Ok(EditorKey::Mouse {
+            x: x - 1,
+            y: y - 1,
+            pressed: byte[0] == b'M',
+            drag: button & 32 != 0,
+        })
+    }
+
+    fn read_paste(&mut self) -> Result<Vec<char>, Box<dyn Error>> {
+        let mut text = vec![];
+        let mut after_cr = false;
+
+        loop {
+            let key = self.read_key()?;
+            match key {
+                EditorKey::PasteEnd => break,
+                EditorKey::Ctrl('j') if after_cr => (),
+                EditorKey::Ctrl('m') | EditorKey::Ctrl('j') => text.push('\n'),
+                EditorKey::Ctrl('i') => text.push('\t'),
+                EditorKey::Other(c) => text.push(c),
+                _ => (),
+            }
+            after_cr = key == EditorKey::Ctrl('m');
+        }
+
+        Ok(text)
+    }
+
+    fn prompt(
+        &mut self,
+        prompt: &str,
+        callback: Option<fn(&mut Editor, &[char], EditorKey)>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        self.prompt_lines(prompt, callback, false)
+    }
+
+    // NOTE: C-j adds a newline to the input if `multiline` is set
+    fn prompt_lines(
+        &mut self,
+        prompt: &str,
+        callback: Option<fn(&mut Editor, &[char], EditorKey)>,
+        multiline: bool,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let mut str_input = String::new();
+        let mut vec_input = vec![];
+        let callback = match callback {
+            Some(f) => f,
+            None => |_: &mut Editor, _: &[char], _: EditorKey| {},
+        };
+
+        loop {
+            let case = if self.search_case_insensitive {
+                " [ci]"
+            } else {
+                ""
+            };
+            set_status_message!(
+                self,
+                "{}{}: {}",
+                prompt,
+                case,
+                str_input.replace('\n', "↵")
+            );
+            self.refresh_screen()?;
+
+            let key = self.read_key()?;
+            match key {
+                EditorKey::Delete
+                | EditorKey::Other(BACKSPACE)
+                | EditorKey::Ctrl('h') => {
+                    str_input.pop();
+                    vec_input.pop();
+                }
+                EditorKey::Other(ESC) => {
+                    set_status_message!(self, "");
+                    callback(self, &vec_input, key);
+                    return Ok(None);
+                }
+                EditorKey::Ctrl('m') if !str_input.is_empty() => {
+                    set_status_message!(self, "");
+                    callback(self, &vec_input, key);
+                    return Ok(Some(str_input));
+                }
+                EditorKey::Other(c) if !c.is_ascii_control() => {
+                    str_input.push(c);
+                    vec_input.push(c);
+                }
+                EditorKey::Ctrl('j') if multiline => {
+                    str_input.push('\n');
+                    vec_input.push('\n');
+                }
+                _ => (),
+            }
+
+            callback(self, &vec_input, key);
+        }
+    }
+
+    fn is_word_separator(&self, c: char) -> bool {
+        match self.buffer().syntax {
+            Some(syntax) => is_separator(syntax, c),
+            None => c.is_whitespace() || RED_SEPARATORS.contains(c),
+        }
+    }
+
+    fn char_after_cursor(&self) -> Option<char> {
+        let row = self.buffer().rows.get(self.buffer().cursor_y)?;
+        Some(
+            row.line
+                .get(self.buffer().cursor_x)
+                .copied()
+                .unwrap_or('\n'),
+        )
+    }
+
+    fn char_before_cursor(&self) -> Option<char> {
+        match self.buffer().cursor_x.checked_sub(1) {
+            Some(x) => self
+                .buffer()
+                .rows
+                .get(self.buffer().cursor_y)?
+                .line
+                .get(x)
+                .copied(),
+            None if self.buffer().cursor_y > 0 => Some('\n'),
+            None => None,
+        }
+    }
+
+    fn forward_word(&mut self) {
+        while self
+            .char_after_cursor()
+            .is_some_and(|c| self.is_word_separator(c))
+        {
+            self.move_cursor(EditorKey::ArrowRight);
+        }
+        while self
+            .char_after_cursor()
+            .is_some_and(|c| !self.is_word_separator(c))
+        {
+            self.move_cursor(EditorKey::ArrowRight);
+        }
+    }
+
+    fn backward_word(&mut self) {
+        while self
+            .char_before_cursor()
+            .is_some_and(|c| self.is_word_separator(c))
+        {
+            self.move_cursor(EditorKey::ArrowLeft);
+        }
+        while self
+            .char_before_cursor()
+            .is_some_and(|c| !self.is_word_separator(c))
+        {
+            self.move_cursor(EditorKey::ArrowLeft);
+        }
+    }
+
+    // NOTE: with wrapped lines, up and down move by screen lines and keep
+    // the column within them
+    fn move_screen_line(&mut self, up: bool) -> bool {
+        let tab_stop = self.tab_width();
+        let row = match self.buffer().rows.get(self.buffer().cursor_y) {
+            Some(row) => row,
+            None => return false,
+        };
+        let render_x =
+            editor_row_cursor_to_render(row, self.buffer().cursor_x, tab_stop);
+        let starts = self.wrap_starts(row);
+        let line = starts.iter().rposition(|&s| s <= render_x).unwrap_or(0);
+        let column = render_x - starts[line];
+
+        let (y, starts, line) = if up && line > 0 {
+            (self.buffer().cursor_y, starts, line - 1)
+        } else if up && self.buffer().cursor_y > 0 {
+            let starts = self
+                .wrap_starts(&self.buffer().rows[self.buffer().cursor_y - 1]);
+            let last = starts.len() - 1;
+            (self.buffer().cursor_y - 1, starts, last)
+        } else if !up && line + 1 < starts.len() {
+            (self.buffer().cursor_y, starts, line + 1)
+        } else if !up && self.buffer().cursor_y + 1 < self.buffer().rows.len() {
+            (self.buffer().cursor_y + 1, vec![0], 0)
+        } else {
+            return false;
+        };
+
+        let end = starts.get(line + 1).map_or(usize::MAX, |&s| s - 1);
+        self.buffer_mut().cursor_y = y;
+        self.buffer_mut().cursor_x = editor_row_render_to_cursor(
+            &self.buffer().rows[y],
+            (starts[line] + column).min(end),
+            tab_stop,
+        );
+        true
+    }
+
+    fn move_cursor(&mut self, key: EditorKey) {
+        if self.wrap_lines
+            && matches!(key, EditorKey::ArrowUp | EditorKey::ArrowDown)
+            && self.move_screen_line(key == EditorKey::ArrowUp)
+        {
+            return;
+        }
+
+        match key {
+            EditorKey::ArrowLeft => {
+                if self.buffer().cursor_x > 0 {
+                    let buffer = self.buffer_mut();
+                    let line = &buffer.rows[buffer.cursor_y].line;
+                    buffer.cursor_x -= 1;
+                    while buffer.cursor_x > 0
+                        && is_combining(line[buffer.cursor_x])
+                    {
+                        buffer.cursor_x -= 1;
+                    }
+                } else if self.buffer().cursor_y > 0 {
+                    self.buffer_mut().cursor_y -= 1;
+                    if let Some(row) =
+                        self.buffer().rows.get(self.buffer().cursor_y)
+                    {
+                        self.buffer_mut().cursor_x = row.line.len();
+                    }
+                }
+            }
+            EditorKey::ArrowRight => {
+                let buffer = self.buffer_mut();
+                if let Some(row) = buffer.rows.get(buffer.cursor_y) {
+                    match buffer.cursor_x.cmp(&row.line.len()) {
+                        Ordering::Less => {
+                            buffer.cursor_x += 1;
+                            while row
+                                .line
+                                .get(buffer.cursor_x)
+                                .is_some_and(|&c| is_combining(c))
+                            {
+                                buffer.cursor_x += 1;
+                            }
+                        }
+                        Ordering::Equal => {
+                            self.buffer_mut().cursor_x = 0;
+                            self.buffer_mut().cursor_y += 1;
+                        }
+                        Ordering::Greater => {}
+                    }
+                }
+            }
+            EditorKey::ArrowUp if self.buffer().cursor_y > 0 => {
+                self.buffer_mut().cursor_y -= 1
+            }
+            EditorKey::ArrowDown
+                if self.buffer().cursor_y < self.buffer().rows.len() =>
+            {
+                self.buffer_mut().cursor_y += 1
+            }
+            _ => (),
+        }
+
+        if let Some(row) = self.buffer().rows.get(self.buffer().cursor_y) {
+            self.buffer_mut().cursor_x =
+                self.buffer().cursor_x.clamp(0, row.line.len());
+        } else {
+            self.buffer_mut().cursor_x = 0;
+        }
+    }
+
+    fn delete_range(&mut self, (begin, end): (Position, Position)) -> usize {
+        self.buffer_mut().cursor_x = end.0;
+        self.buffer_mut().cursor_y = end.1;
+
+        let mut deleted = 0;
+        while (self.buffer().cursor_x, self.buffer().cursor_y) != begin {
+            self.delete_char();
+            deleted += 1;
+        }
+
+        deleted
+    }
+
+    fn copy_range(&mut self, (begin, end): (Position, Position)) {
+        let old_pos = (self.buffer().cursor_x, self.buffer().cursor_y);
+        self.buffer_mut().cursor_x = begin.0;
+        self.buffer_mut().cursor_y = begin.1;
+        let mut copy = String::new();
+
+        while (self.buffer().cursor_x, self.buffer().cursor_y) != end {
+            if let Some(row) = self.buffer().rows.get(self.buffer().cursor_y) {
+                if self.buffer().cursor_x >= row.line.len() {
+                    copy.push('\n')
+                } else {
+                    copy.push(row.line[self.buffer().cursor_x])
+                }
+            }
+            self.move_cursor(EditorKey::ArrowRight);
+        }
+
+        self.buffer_mut().mark = None;
+        self.push_kill(copy);
+        self.buffer_mut().cursor_x = old_pos.0;
+        self.buffer_mut().cursor_y = old_pos.1;
+    }
+
+    fn delete_word_backward(&mut self) -> usize {
+        // NOTE: at the start of a line only join with the previous one
+        if self.buffer().cursor_x == 0 {
+            self.delete_char();
+            return 1;
+        }
+
+        let end = (self.buffer().cursor_x, self.buffer().cursor_y);
+        self.backward_word();
+        // NOTE: take the indentation along with the first word of a line
+        if self.buffer().cursor_y == end.1
+            && self.buffer().rows[self.buffer().cursor_y].line
+                [..self.buffer().cursor_x]
+                .iter()
+                .all(|c| c.is_whitespace())
+        {
+            self.buffer_mut().cursor_x = 0;
+        }
+        let begin = (self.buffer().cursor_x, self.buffer().cursor_y);
+
+        self.delete_range((begin, end))
+    }
+
+    // NOTE: consecutive kills are collected in the clipboard
+    fn kill_line(&mut self, append: bool) -> Result<usize, Box<dyn Error>> {
+        let begin = (self.buffer().cursor_x, self.buffer().cursor_y);
+        let end = match self.buffer().rows.get(self.buffer().cursor_y) {
+            Some(row) if self.buffer().cursor_x < row.line.len() => {
+                (row.line.len(), self.buffer().cursor_y)
+            }
+            // NOTE: at the end of a line kill the newline instead
+            Some(_)
+                if self.buffer().cursor_y + 1 < self.buffer().rows.len() =>
+            {
+                (0, self.buffer().cursor_y + 1)
+            }
+            _ => return Ok(0),
+        };
+
+        self.copy_range((begin, end));
+        if append && self.kill_ring.len() > 1 {
+            let killed = self.kill_ring.pop().unwrap_or_default();
+            self.kill_index = self.kill_ring.len() - 1;
+            self.kill_ring[self.kill_index].push_str(&killed);
+        }
+        self.copy_to_system_clipboard()?;
+        Ok(self.delete_range((begin, end)))
+    }
+
+    fn clipboard(&self) -> &str {
+        self.kill_ring.get(self.kill_index).map_or("", |text| text)
+    }
+
+    fn push_kill(&mut self, text: String) {
+        if self.kill_ring.len() == RED_KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.kill_ring.push(text);
+        self.kill_index = self.kill_ring.len() - 1;
+    }
+
+    fn copy_to_system_clipboard(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.use_system_clipboard {
+            return Ok(());
+        }
+
+        match osc52_copy(self.clipboard(), &self.terminal) {
+            Some(sequence) => {
+                self.stdout.write_all(&sequence)?;
+                self.stdout.flush()?;
+            }
+            None => {
+                set_status_message!(
+                    self,
+                    "Selection is too large for the system clipboard"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn paste_from_system_clipboard(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.use_system_clipboard || !self.config.clipboard_read {
+            return Ok(());
+        }
+
+        self.stdout.write_all(OSC52_REQUEST)?;
+        self.stdout.flush()?;
+
+        // NOTE: reads time out after a tenth of a second in raw mode, so a
+        // terminal that doesn't answer leaves the internal clipboard alone
+        let mut input = vec![];
+        let mut byte = [0; 1];
+        let mut reply = None;
+        while reply.is_none() && self.stdin.read(&mut byte)? == 1 {
+            input.push(byte[0]);
+            reply = osc52_reply_range(&input);
+        }
+
+        let text = reply.and_then(|range| {
+            let text = osc52_parse_reply(&input[range.clone()]);
+            input.drain(range);
+            text
+        });
+        match text {
+            Some(text) if text != self.clipboard() => self.push_kill(text),
+            _ => (),
+        }
+        if !input.is_empty() {
+            self.unread(input);
+        }
+
+        Ok(())
+    }
+
+    // NOTE: the bytes are read again before the rest of stdin
+    fn unread(&mut self, bytes: Vec<u8>) {
+        let stdin = std::mem::replace(&mut self.stdin, Box::new(io::empty()));
+        self.stdin = Box::new(io::Cursor::new(bytes).chain(stdin));
+    }
+
+    fn insert_chars(&mut self, chars: impl Iterator<Item = char>) {
+        for c in chars {
+            match c {
+                '\n' => self.insert_newline(),
+                _ => self.insert_char(c),
+            }
+        }
+    }
+
+    fn duplicate_line(&mut self) {
+        let line = match self.buffer().rows.get(self.buffer().cursor_y) {
+            Some(row) => row.line.clone(),
+            None => return,
+        };
+
+        let x = self.buffer().cursor_x;
+        self.buffer_mut().cursor_x = line.len();
+        self.insert_newline();
+        self.insert_chars(line.into_iter());
+        self.buffer_mut().cursor_x = x;
+    }
+
+    fn is_code(&self, (x, y): Position) -> bool {
+        let row = &self.buffer().rows[y];
+        let render_x = editor_row_cursor_to_render(row, x, self.tab_width());
+        !matches!(
+            row.highlights.get(render_x),
+            Some(
+                Highlight::String
+                    | Highlight::Comment
+                    | Highlight::MultiLineComment
+            )
+        )
+    }
+
+    // NOTE: Brackets in strings and comments only match each other. Only the
+    // given rows are searched and their highlights have to be up to date.
+    fn matching_bracket(&self, rows: Range<usize>) -> Option<Position> {
+        let (x, y) = (self.buffer().cursor_x, self.buffer().cursor_y);
+        let bracket = *self.buffer().rows.get(y)?.line.get(x)?;
+        let (open, close) = [('(', ')'), ('[', ']'), ('{', '}')]
+            .iter()
+            .copied()
+            .find(|&(open, close)| bracket == open || bracket == close)?;
+        let other = if bracket == open { close } else { open };
+        let is_code = self.is_code((x, y));
+
+        let mut depth = 0;
+        let mut matches = |pos: Position| {
+            let c = self.buffer().rows[pos.1].line[pos.0];
+            if self.is_code(pos) != is_code {
+                return false;
+            } else if c == bracket {
+                depth += 1;
+            } else if c == other {
+                depth -= 1;
+            }
+            depth == 0
+        };
+
+        if bracket == open {
+            (y..rows.end.min(self.buffer().rows.len()))
+                .flat_map(|row| {
+                    (0..self.buffer().rows[row].line.len())
+                        .map(move |col| (col, row))
+                })
+                .skip(x)
+                .find(|&pos| matches(pos))
+        } else {
+            (rows.start..=y)
+                .rev()
+                .flat_map(|row| {
+                    let len = self.buffer().rows[row].line.len();
+                    (0..len).rev().map(move |col| (col, row))
+                })
+                .skip(self.buffer().rows[y].line.len() - x - 1)
+                .find(|&pos| matches(pos))
+        }
+    }
+
+    // NOTE: The partner is overlaid while drawing, so the row highlights (and
+    // the search state in stored_hl) are never touched. A partner outside of
+    // the screen isn't shown anyway, so the search stops at its edges.
+    fn matching_bracket_render_pos(&self) -> Option<Position> {
+        let visible = self.buffer().row_offset
+            ..self.buffer().row_offset + self.pane_rows();
+        let (x, y) = self.matching_bracket(visible)?;
+        let render_x = editor_row_cursor_to_render(
+            &self.buffer().rows[y],
+            x,
+            self.tab_width(),
+        );
+        Some((render_x, y))
+    }
+
+    fn jump_to_matching_bracket(&mut self) {
+        // NOTE: brackets in comments below the screen have to be skipped
+        self.update_stale_rows(self.buffer().rows.len());
+        match self.matching_bracket(0..self.buffer().rows.len()) {
+            Some((x, y)) => {
+                self.buffer_mut().cursor_x = x;
+                self.buffer_mut().cursor_y = y;
+            }
+            None => {
+                set_status_message!(self, "No matching bracket");
+            }
+        }
+    }
+
+    fn move_line(&mut self, up: bool) {
+        let (above, below) = match up {
+            true if self.buffer().cursor_y > 0 => {
+                (self.buffer().cursor_y - 1, self.buffer().cursor_y)
+            }
+            false => (self.buffer().cursor_y, self.buffer().cursor_y + 1),
+            _ => return,
+        };
+        if below >= self.buffer().rows.len() {
+            return;
+        }
+
+        let mut text = self.buffer().rows[below].line.clone();
+        text.push('\n');
+        self.record_edit(Edit::Delete((0, below), text.clone()));
+        self.splice_delete((0, below), &text);
+        self.record_edit(Edit::Insert((0, above), text.clone()));
+        self.splice_insert((0, above), &text);
+
+        self.buffer_mut().cursor_y = if up { above } else { below };
+        self.mark_dirty();
+    }
+
+    fn selected_lines(&self) -> (usize, usize) {
+        match self.selection() {
+            // NOTE: a selection ending at the start of a line excludes it
+            Some((begin, end)) if end.0 == 0 && end.1 > begin.1 => {
+                (begin.1, end.1 - 1)
+            }
+            Some((begin, end)) => (begin.1, end.1),
+            None => (self.buffer().cursor_y, self.buffer().cursor_y),
+        }
+    }
+
+    // NOTE: the selection stays active so the lines can be shifted again
+    fn shift_lines(&mut self, indent: bool) {
+        let mark = self.buffer().mark;
+        let (first, last) = self.selected_lines();
+        let tab_width = self.tab_width();
+
+        for y in first..=last.min(self.buffer().rows.len().saturating_sub(1)) {
+            let line = &self.buffer().rows[y].line;
+            let text = if indent {
+                if line.is_empty() {
+                    continue;
+                }
+                self.indent_unit()
+            } else if line.first() == Some(&'\t') {
+                vec!['\t']
+            } else {
+                let spaces = line.iter().take_while(|&&c| c == ' ').count();
+                vec![' '; spaces.min(tab_width)]
+            };
+            if text.is_empty() {
+                continue;
+            }
+
+            if indent {
+                self.record_edit(Edit::Insert((0, y), text.clone()));
+                self.splice_insert((0, y), &text);
+            } else {
+                self.record_edit(Edit::Delete((0, y), text.clone()));
+                self.splice_delete((0, y), &text);
+            }
+
+            if y == self.buffer().cursor_y {
+                self.buffer_mut().cursor_x = match indent {
+                    true => self.buffer().cursor_x + text.len(),
+                    false => self.buffer().cursor_x.saturating_sub(text.len()),
+                };
+            }
+            self.mark_dirty();
+        }
+
+        self.buffer_mut().mark = mark;
+    }
+
+    fn toggle_comment(&mut self) {
+        let token = match self.buffer().syntax {
+            Some(syntax) if !syntax.single_line_comment.is_empty() => {
+                syntax.single_line_comment
+            }
+            _ => {
+                set_status_message!(self, "No line comments for this filetype");
+                return;
+            }
+        };
+        let token: Vec<char> = token.chars().collect();
+
+        let (first, last) = self.selected_lines();
+        let last = last.min(self.buffer().rows.len().saturating_sub(1));
+        let is_blank = |row: &Row| row.line.iter().all(|c| c.is_whitespace());
+        let lines: Vec<usize> = (first..=last)
+            .filter(|&y| first == last || !is_blank(&self.buffer().rows[y]))
+            .collect();
+        if lines.is_empty() || self.buffer().rows.is_empty() {
+            return;
+        }
+
+        let commented = lines.iter().all(|&y| {
+            let indent = self.indentation(y).len();
+            self.buffer().rows[y].line[indent..].starts_with(&token)
+        });
+
+        for y in lines {
+            let indent = self.indentation(y).len();
+            let mut text = token.clone();
+            text.push(' ');
+
+            let delta = if commented {
+                let line = &self.buffer().rows[y].line[indent + token.len()..];
+                if !line.starts_with(&[' ']) {
+                    text.pop();
+                }
+                self.record_edit(Edit::Delete((indent, y), text.clone()));
+                self.splice_delete((indent, y), &text);
+                -(text.len() as isize)
+            } else {
+                self.record_edit(Edit::Insert((indent, y), text.clone()));
+                self.splice_insert((indent, y), &text);
+                text.len() as isize
+            };
+
+            if y == self.buffer().cursor_y && self.buffer().cursor_x >= indent {
+                self.buffer_mut().cursor_x =
+                    (self.buffer().cursor_x as isize + delta)
+                        .max(indent as isize) as usize;
+            }
+        }
+
+        self.mark_dirty();
+    }
+
+    fn indent_unit(&self) -> Vec<char> {
+        if self.soft_tabs {
+            vec![' '; self.tab_width()]
+        } else {
+            vec!['\t']
+        }
+    }
+
+    fn tab_text(&self) -> Vec<char> {
+        if !self.soft_tabs {
+            return vec!['\t'];
+        }
+
+        let tab_stop = self.tab_width();
+        let render_x =
+            self.buffer()
+                .rows
+                .get(self.buffer().cursor_y)
+                .map_or(0, |row| {
+                    editor_row_cursor_to_render(
+                        row,
+                        self.buffer().cursor_x,
+                        tab_stop,
+                    )
+                });
+        vec![' '; tab_stop - render_x % tab_stop]
+    }
+
+    fn paste(&mut self) -> Vec<char> {
+        let text: Vec<char> = self.clipboard().chars().collect();
+        let begin = (self.buffer().cursor_x, self.buffer().cursor_y);
+        self.insert_chars(text.iter().copied());
+        self.last_yank =
+            Some((begin, (self.buffer().cursor_x, self.buffer().cursor_y)));
+        text
+    }
+
+    // NOTE: replace the text that was just pasted with an older kill
+    fn yank_pop(&mut self, last_yank: Option<(Position, Position)>) {
+        let yank = match last_yank {
+            Some(yank) if self.kill_ring.len() > 1 => yank,
+            Some(_) => return,
+            None => {
+                set_status_message!(self, "Previous command was not a paste");
+                return;
+            }
         };
 
-        loop {
-            set_status_message!(self, "{}: {}", prompt, str_input);
-            self.refresh_screen()?;
+        self.delete_range(yank);
+        self.kill_index = self
+            .kill_index
+            .checked_sub(1)
+            .unwrap_or(self.kill_ring.len() - 1);
+        let text = self.paste();
+        self.record_change(Change::Insert(text), false);
+    }
 
-            let key = self.read_key()?;
-            match key {
-                EditorKey::Delete
-                | EditorKey::Other(BACKSPACE)
-                | EditorKey::Ctrl('h') => {
-                    str_input.pop();
-                    vec_input.pop();
-                }
-                EditorKey::Other(ESC) => {
-                    set_status_message!(self, "");
-                    callback(self, &vec_input, key);
-                    return Ok(None);
-                }
-                EditorKey::Ctrl('m') if !str_input.is_empty() => {
-                    set_status_message!(self, "");
-                    callback(self, &vec_input, key);
-                    return Ok(Some(str_input));
+    fn record_change(&mut self, change: Change, extend: bool) {
+        self.extend_change = true;
+
+        if extend {
+            match (&mut self.last_change, &change) {
+                (Some(Change::Insert(text)), Change::Insert(more)) => {
+                    text.extend(more);
+                    return;
                 }
-                EditorKey::Other(c) if !c.is_ascii_control() => {
-                    str_input.push(c as char);
-                    vec_input.push(c as char);
+                (
+                    Some(Change::DeleteBackward(n)),
+                    Change::DeleteBackward(m),
+                )
+                | (Some(Change::DeleteForward(n)), Change::DeleteForward(m))
+                | (Some(Change::Kill(n)), Change::Kill(m)) => {
+                    *n += m;
+                    return;
                 }
                 _ => (),
             }
-
-            callback(self, &vec_input, key);
         }
+
+        self.last_change = Some(change);
     }
 
-    fn move_cursor(&mut self, key: EditorKey) {
-        match key {
-            EditorKey::ArrowLeft => {
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                } else if self.cursor_y > 0 {
-                    self.cursor_y -= 1;
-                    if let Some(row) = self.rows.get(self.cursor_y) {
-                        self.cursor_x = row.line.len();
-                    }
-                }
+    fn record_edit(&mut self, edit: Edit) {
+        let cursor = (self.buffer().cursor_x, self.buffer().cursor_y);
+        let limit = self.config.undo_limit;
+        self.buffer_mut().history.record(edit, cursor, limit);
+    }
+
+    fn splice_insert(&mut self, (x, y): Position, text: &[char]) {
+        let mut lines = text.split(|&c| c == '\n').map(<[char]>::to_vec);
+        let mut new_lines = vec![];
+        let first_new = if y < self.buffer().rows.len() {
+            let row = &mut self.buffer_mut().rows[y];
+            let suffix = row.line.split_off(x);
+            row.line.extend(lines.next().unwrap_or_default());
+            new_lines.extend(lines);
+            match new_lines.last_mut() {
+                Some(last) => last.extend(suffix),
+                None => self.buffer_mut().rows[y].line.extend(suffix),
             }
-            EditorKey::ArrowRight => {
-                if let Some(row) = self.rows.get(self.cursor_y) {
-                    match self.cursor_x.cmp(&row.line.len()) {
-                        Ordering::Less => self.cursor_x += 1,
-                        Ordering::Equal => {
-                            self.cursor_x = 0;
-                            self.cursor_y += 1;
-                        }
-                        Ordering::Greater => {}
-                    }
-                }
+            y + 1
+        } else {
+            // NOTE: text after the last line always ends with a newline
+            new_lines.extend(lines);
+            new_lines.pop();
+            y
+        };
+
+        let last_new = first_new + new_lines.len();
+        self.buffer_mut().rows.splice(
+            first_new..first_new,
+            new_lines.into_iter().map(|line| Row {
+                line,
+                ..Row::empty()
+            }),
+        );
+        for idx in y..last_new {
+            self.update_row(idx);
+        }
+        self.mark_stale(last_new);
+    }
+
+    // NOTE: the row now follows a different one, so its comment state has
+    // to be checked again
+    fn mark_stale(&mut self, row_idx: usize) {
+        if let Some(row) = self.buffer_mut().rows.get_mut(row_idx) {
+            row.stale = true;
+        }
+    }
+
+    fn splice_delete(&mut self, (x, y): Position, text: &[char]) {
+        let end = text_end((x, y), text);
+        if end.1 < self.buffer().rows.len() {
+            let suffix = self.buffer_mut().rows[end.1].line.split_off(end.0);
+            self.buffer_mut().rows[y].line.truncate(x);
+            self.buffer_mut().rows[y].line.extend(suffix);
+            self.buffer_mut().rows.drain(y + 1..=end.1);
+            self.mark_stale(y + 1);
+        } else if x > 0 {
+            self.buffer_mut().rows[y].line.truncate(x);
+            self.buffer_mut().rows.truncate(y + 1);
+        } else {
+            self.buffer_mut().rows.truncate(y);
+        }
+
+        if y < self.buffer().rows.len() {
+            self.update_row(y);
+        }
+    }
+
+    fn undo(&mut self) {
+        let action = match self.buffer_mut().history.pop_undo() {
+            Some(action) => action,
+            None => {
+                set_status_message!(self, "Nothing to undo");
+                return;
             }
-            EditorKey::ArrowUp if self.cursor_y > 0 => self.cursor_y -= 1,
-            EditorKey::ArrowDown if self.cursor_y < self.rows.len() => {
-                self.cursor_y += 1
+        };
+
+        for edit in action.edits.iter().rev() {
+            match edit {
+                Edit::Insert(pos, text) => self.splice_delete(*pos, text),
+                Edit::Delete(pos, text) => self.splice_insert(*pos, text),
             }
-            _ => (),
         }
 
-        if let Some(row) = self.rows.get(self.cursor_y) {
-            self.cursor_x = self.cursor_x.clamp(0, row.line.len());
-        } else {
-            self.cursor_x = 0;
+        self.buffer_mut().cursor_x = action.cursor.0;
+        self.buffer_mut().cursor_y = action.cursor.1;
+        self.buffer_mut().history.push_redo(action);
+        self.buffer_mut().mark = None;
+        self.buffer_mut().dirty = !self.buffer().history.is_saved();
+    }
+
+    fn redo(&mut self) {
+        let action = match self.buffer_mut().history.pop_redo() {
+            Some(action) => action,
+            None => {
+                set_status_message!(self, "Nothing to redo");
+                return;
+            }
+        };
+
+        for edit in action.edits.iter() {
+            let (x, y) = match edit {
+                Edit::Insert(pos, text) => {
+                    self.splice_insert(*pos, text);
+                    text_end(*pos, text)
+                }
+                Edit::Delete(pos, text) => {
+                    self.splice_delete(*pos, text);
+                    *pos
+                }
+            };
+            self.buffer_mut().cursor_x = x;
+            self.buffer_mut().cursor_y = y;
         }
+
+        self.buffer_mut().history.push_undo(action);
+        self.buffer_mut().mark = None;
+        self.buffer_mut().dirty = !self.buffer().history.is_saved();
     }
 
-    fn delete_range(&mut self, (begin, end): (Position, Position)) {
-        self.cursor_x = end.0;
-        self.cursor_y = end.1;
+    fn repeat_last_change(&mut self) {
+        match self.last_change.clone() {
+            Some(Change::Insert(text)) => self.insert_chars(text.into_iter()),
+            Some(Change::DeleteBackward(n)) => {
+                for _ in 0..n {
+                    self.delete_char();
+                }
+            }
+            Some(Change::DeleteForward(n) | Change::Kill(n)) => {
+                for _ in 0..n {
+                    self.move_cursor(EditorKey::ArrowRight);
+                    self.delete_char();
+                }
+            }
+            None => {
+                set_status_message!(self, "No change to repeat");
+            }
+        }
+    }
 
-        while (self.cursor_x, self.cursor_y) != begin {
-            self.delete_char();
+    fn is_writable(&mut self) -> bool {
+        if self.buffer().follow.is_some() {
+            set_status_message!(self, "Buffer is read-only while following");
+            false
+        } else if self.read_only {
+            set_status_message!(self, "Buffer is read-only");
+            false
+        } else {
+            true
         }
     }
 
-    fn copy_range(&mut self, (begin, end): (Position, Position)) {
-        let old_pos = (self.cursor_x, self.cursor_y);
-        self.cursor_x = begin.0;
-        self.cursor_y = begin.1;
-        let mut copy = String::new();
+    fn process_prefix_x(&mut self) -> Result<(), Box<dyn Error>> {
+        set_status_message!(self, "C-x-");
+        self.refresh_screen()?;
 
-        while (self.cursor_x, self.cursor_y) != end {
-            if let Some(row) = self.rows.get(self.cursor_y) {
-                if self.cursor_x >= row.line.len() {
-                    copy.push('\n')
+        match self.read_key()? {
+            EditorKey::Other('o') => {
+                if self.split.is_some() {
+                    self.focus_other_pane();
+                } else if self.hsplit.is_some() {
+                    self.focus_other_view();
                 } else {
-                    copy.push(row.line[self.cursor_x])
+                    set_status_message!(self, "There is no other window");
                 }
             }
-            self.move_cursor(EditorKey::ArrowRight);
+            EditorKey::Other('1') => self.close_other_window(),
+            EditorKey::Other('2') => self.split_view(),
+            EditorKey::Ctrl('r') => self.reload()?,
+            EditorKey::Ctrl('f') => self.find_file()?,
+            EditorKey::Other('b') => self.next_buffer(),
+            EditorKey::Ctrl('d') => {
+                if self.is_writable() {
+                    self.duplicate_line();
+                }
+            }
+            // NOTE: most terminals send C-; as a plain ;
+            EditorKey::Other(';') => {
+                if self.is_writable() {
+                    self.toggle_comment();
+                }
+            }
+            EditorKey::Other(ESC) | EditorKey::Ctrl('g') => {
+                set_status_message!(self, "");
+            }
+            EditorKey::Ctrl(c) => {
+                set_status_message!(self, "C-x C-{} isn't bound!", c);
+            }
+            key => {
+                set_status_message!(self, "C-x {:?} isn't bound!", key);
+            }
         }
 
-        self.mark = None;
-        self.clipboard = copy;
-        self.cursor_x = old_pos.0;
-        self.cursor_y = old_pos.1;
+        Ok(())
     }
 
-    fn paste(&mut self) {
-        let mut clipboard = std::mem::take(&mut self.clipboard);
-        for c in clipboard.chars() {
-            match c {
-                '\n' => self.insert_newline(),
-                _ => self.insert_char(c),
+    fn toggle_option(&mut self, key: char) {
+        match key {
+            'w' => {
+                self.wrap_lines = !self.wrap_lines;
+                set_status_message!(
+                    self,
+                    "Line wrapping {}",
+                    if self.wrap_lines { "on" } else { "off" }
+                );
+            }
+            'l' => {
+                self.line_numbers = !self.line_numbers;
+                set_status_message!(
+                    self,
+                    "Line numbers are {}",
+                    if self.line_numbers { "shown" } else { "hidden" }
+                );
+            }
+            'c' => {
+                self.cursor_line = !self.cursor_line;
+                set_status_message!(
+                    self,
+                    "Cursor line highlight {}",
+                    if self.cursor_line { "on" } else { "off" }
+                );
+            }
+            's' => {
+                self.show_tabs = !self.show_tabs;
+                set_status_message!(
+                    self,
+                    "Tabs are {}",
+                    if self.show_tabs { "shown" } else { "hidden" }
+                );
+            }
+            _ => {
+                self.soft_tabs = !self.soft_tabs;
+                set_status_message!(
+                    self,
+                    "Tab inserts {}",
+                    if self.soft_tabs { "spaces" } else { "a tab" }
+                );
             }
         }
-        self.clipboard = std::mem::take(&mut clipboard);
     }
 
     fn process_keypress(
         &mut self,
         key: EditorKey,
     ) -> Result<bool, Box<dyn Error>> {
+        self.col_offset_locked = self.config.sticky_scroll
+            && matches!(
+                key,
+                EditorKey::ArrowUp
+                    | EditorKey::ArrowDown
+                    | EditorKey::PageUp
+                    | EditorKey::PageDown
+            );
+
+        if key.modifies_buffer() && !self.is_writable() {
+            return Ok(true);
+        }
+
+        // NOTE: a selection made with shift ends with the next plain motion
+        if self.shift_selection
+            && matches!(
+                key,
+                EditorKey::ArrowLeft
+                    | EditorKey::ArrowRight
+                    | EditorKey::ArrowUp
+                    | EditorKey::ArrowDown
+                    | EditorKey::Home
+                    | EditorKey::End
+                    | EditorKey::PageUp
+                    | EditorKey::PageDown
+            )
+        {
+            self.buffer_mut().mark = None;
+        }
+
+        self.last_keypress = SystemTime::now();
+        self.autosave_failed = false;
+        let extend_change = std::mem::take(&mut self.extend_change);
+        let last_yank = self.last_yank.take();
+        let recenter_count = std::mem::take(&mut self.recenter_count);
+        self.buffer_mut()
+            .history
+            .start_keypress(matches!(key, EditorKey::Other(c) if c != ESC));
+
         match key {
             EditorKey::Ctrl('m') => {
+                let indent = self.new_line_indentation();
+
                 self.insert_newline();
+                self.insert_chars(indent.iter().copied());
+
+                let mut text = vec!['\n'];
+                text.extend(indent);
+                self.record_change(Change::Insert(text), extend_change);
             }
             EditorKey::Ctrl('q') => {
-                if self.dirty && self.quit_times > 0 {
+                if self.is_any_buffer_dirty() && self.quit_times > 0 {
                     set_status_message!(
                         self,
                         "WARNING!!! File has unsaved changes. \
@@ -1172,6 +3704,7 @@ impl<'i, 'o> Editor<'i, 'o> {
                     self.quit_times -= 1;
                     return Ok(true);
                 } else {
+                    self.remember_positions();
                     clear_screen(&mut self.stdout)?;
                     return Ok(false);
                 }
@@ -1180,38 +3713,63 @@ impl<'i, 'o> Editor<'i, 'o> {
                 self.save()?;
             }
             EditorKey::Home => {
-                self.cursor_x = 0;
+                // NOTE: toggle between the indentation and the first column
+                let indent = self
+                    .buffer()
+                    .rows
+                    .get(self.buffer().cursor_y)
+                    .map_or(0, |row| {
+                        match row.line.iter().position(|c| !c.is_whitespace()) {
+                            Some(indent)
+                                if indent != self.buffer().cursor_x =>
+                            {
+                                indent
+                            }
+                            _ => 0,
+                        }
+                    });
+                self.buffer_mut().cursor_x = indent;
             }
             EditorKey::End => {
-                if let Some(row) = self.rows.get(self.cursor_y) {
-                    self.cursor_x = row.line.len();
+                if let Some(row) =
+                    self.buffer().rows.get(self.buffer().cursor_y)
+                {
+                    self.buffer_mut().cursor_x = row.line.len();
                 }
             }
             EditorKey::Ctrl('f') => self.find()?,
+            EditorKey::Ctrl('g') | EditorKey::Meta('g') => self.goto_line()?,
+            EditorKey::Ctrl('r') | EditorKey::Meta('r') => self.replace()?,
             EditorKey::Delete
             | EditorKey::Other(BACKSPACE)
             | EditorKey::Ctrl('h') => {
                 if let Some(selection) = self.selection() {
-                    self.delete_range(selection);
+                    let deleted = self.delete_range(selection);
+                    self.record_change(Change::DeleteForward(deleted), false);
+                } else if key == EditorKey::Delete {
+                    self.move_cursor(EditorKey::ArrowRight);
+                    self.delete_char();
+                    self.record_change(Change::DeleteForward(1), extend_change);
                 } else {
-                    if key == EditorKey::Delete {
-                        self.move_cursor(EditorKey::ArrowRight);
-                    }
                     self.delete_char();
+                    self.record_change(
+                        Change::DeleteBackward(1),
+                        extend_change,
+                    );
                 }
             }
             EditorKey::PageUp | EditorKey::PageDown => {
                 if key == EditorKey::PageUp {
-                    self.cursor_y = self.row_offset;
+                    self.buffer_mut().cursor_y = self.buffer().row_offset;
                 } else if key == EditorKey::PageDown {
-                    self.cursor_y = usize::clamp(
-                        self.row_offset + self.screen_rows - 1,
+                    self.buffer_mut().cursor_y = usize::clamp(
+                        self.buffer().row_offset + self.pane_rows() - 1,
                         0,
-                        self.rows.len(),
+                        self.buffer().rows.len(),
                     );
                 }
 
-                for _ in 0..self.screen_rows {
+                for _ in 0..self.pane_rows() {
                     self.move_cursor(if key == EditorKey::PageUp {
                         EditorKey::ArrowUp
                     } else {
@@ -1225,22 +3783,129 @@ impl<'i, 'o> Editor<'i, 'o> {
             | EditorKey::ArrowDown => {
                 self.move_cursor(key);
             }
-            EditorKey::Other(ESC) | EditorKey::Ctrl('l') => (),
+            EditorKey::Ctrl('l') => self.recenter(recenter_count),
+            EditorKey::Other(ESC) => (),
+            EditorKey::PasteStart => {
+                let text = self.read_paste()?;
+                self.insert_chars(text.iter().copied());
+                self.record_change(Change::Insert(text), false);
+            }
+            EditorKey::PasteEnd => (),
             EditorKey::Ctrl(' ') => {
-                if let Some(row) = self.rows.get(self.cursor_y) {
-                    self.mark = Some((
-                        editor_row_cursor_to_render(row, self.cursor_x),
-                        self.cursor_y,
-                    ));
+                if self.buffer().cursor_y < self.buffer().rows.len() {
+                    self.buffer_mut().mark =
+                        Some((self.buffer().cursor_x, self.buffer().cursor_y));
+                    self.shift_selection = false;
+                }
+            }
+            EditorKey::ShiftArrowLeft
+            | EditorKey::ShiftArrowRight
+            | EditorKey::ShiftArrowUp
+            | EditorKey::ShiftArrowDown => {
+                if self.buffer().mark.is_none() {
+                    self.buffer_mut().mark =
+                        Some((self.buffer().cursor_x, self.buffer().cursor_y));
+                    self.shift_selection = true;
                 }
+                self.move_cursor(match key {
+                    EditorKey::ShiftArrowLeft => EditorKey::ArrowLeft,
+                    EditorKey::ShiftArrowRight => EditorKey::ArrowRight,
+                    EditorKey::ShiftArrowUp => EditorKey::ArrowUp,
+                    _ => EditorKey::ArrowDown,
+                });
             }
             EditorKey::Ctrl('c') => {
                 if let Some(selection) = self.selection() {
                     self.copy_range(selection);
+                    self.copy_to_system_clipboard()?;
+                } else if let Some(row) =
+                    self.buffer().rows.get(self.buffer().cursor_y)
+                {
+                    // NOTE: without a selection copy the whole line
+                    let mut line: String = row.line.iter().collect();
+                    line.push('\n');
+                    self.push_kill(line);
+                    self.copy_to_system_clipboard()?;
                 }
             }
             EditorKey::Ctrl('v') => {
-                self.paste();
+                self.paste_from_system_clipboard()?;
+                let text = self.paste();
+                self.record_change(Change::Insert(text), false);
+            }
+            EditorKey::Ctrl('i')
+                if self
+                    .selection()
+                    .is_some_and(|(begin, end)| begin.1 != end.1) =>
+            {
+                self.shift_lines(true);
+            }
+            EditorKey::BackTab => self.shift_lines(false),
+            EditorKey::Ctrl('i') => {
+                let text = self.tab_text();
+                self.insert_chars(text.iter().copied());
+                self.record_change(Change::Insert(text), extend_change);
+            }
+            EditorKey::Meta(c @ ('w' | 'l' | 'c' | 's' | 'i')) => {
+                self.toggle_option(c)
+            }
+            EditorKey::Ctrl('w') | EditorKey::Meta(BACKSPACE) => {
+                match self.selection() {
+                    Some(selection) if key == EditorKey::Ctrl('w') => {
+                        self.copy_range(selection);
+                        self.copy_to_system_clipboard()?;
+                        let deleted = self.delete_range(selection);
+                        self.record_change(
+                            Change::DeleteForward(deleted),
+                            false,
+                        );
+                    }
+                    _ => {
+                        let deleted = self.delete_word_backward();
+                        self.record_change(
+                            Change::DeleteBackward(deleted),
+                            false,
+                        );
+                    }
+                }
+            }
+            EditorKey::Ctrl('k') => {
+                let append = extend_change
+                    && matches!(self.last_change, Some(Change::Kill(_)));
+                let deleted = self.kill_line(append)?;
+                self.record_change(Change::Kill(deleted), append);
+            }
+            EditorKey::Meta('%') | EditorKey::Meta('m') => {
+                self.jump_to_matching_bracket()
+            }
+            EditorKey::Meta('p') => self.move_line(true),
+            EditorKey::Meta('n') => self.move_line(false),
+            EditorKey::Meta('d') => self.duplicate_line(),
+            EditorKey::Meta('e') => self.reload()?,
+            EditorKey::Meta('y') => self.yank_pop(last_yank),
+            EditorKey::Ctrl('/') => self.toggle_comment(),
+            EditorKey::Meta('f') => self.forward_word(),
+            EditorKey::Meta('b') => self.backward_word(),
+            EditorKey::Mouse {
+                x,
+                y,
+                pressed,
+                drag,
+            } => self.click((x, y), pressed, drag),
+            EditorKey::WheelUp => self.scroll_view(true),
+            EditorKey::WheelDown => self.scroll_view(false),
+            EditorKey::Meta('.') => self.repeat_last_change(),
+            EditorKey::Ctrl('z') => self.undo(),
+            EditorKey::Ctrl('y') | EditorKey::Meta('z') => self.redo(),
+            EditorKey::Ctrl('x') => self.process_prefix_x()?,
+            EditorKey::Meta(':') => self.set_filetype()?,
+            EditorKey::Meta('t') => {
+                let message = match self.trim_trailing_blank_lines() {
+                    0 => "No trailing blank lines".to_string(),
+                    1 => "Removed 1 trailing blank line".to_string(),
+                    n => format!("Removed {} trailing blank lines", n),
+                };
+                set_status_message!(self, "{}", message);
             }
             EditorKey::Meta(c) => {
                 set_status_message!(self, "M-{} isn't bound!", c);
@@ -1249,7 +3914,8 @@ impl<'i, 'o> Editor<'i, 'o> {
                 set_status_message!(self, "C-{} isn't bound!", c);
             }
             EditorKey::Other(byte) => {
-                self.insert_char(byte as char);
+                self.insert_char(byte);
+                self.record_change(Change::Insert(vec![byte]), extend_change);
             }
         }
 
@@ -1299,158 +3965,506 @@ fn parse_utf8(
     })
 }
 
-fn clear_screen(dest: &mut impl Write) -> Result<(), Box<dyn Error>> {
-    dest.write_all(ESC_SEQ_CLEAR_SCREEN)?;
-    dest.write_all(ESC_SEQ_RESET_CURSOR)?;
-    dest.flush()?;
+fn clear_screen(dest: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    dest.write_all(ESC_SEQ_CLEAR_SCREEN)?;
+    dest.write_all(ESC_SEQ_RESET_CURSOR)?;
+    dest.flush()?;
+
+    Ok(())
+}
+
+impl<'i, 'o> Editor<'i, 'o> {
+    fn line_number_sep_len() -> usize {
+        RED_LINE_SEP.chars().count()
+    }
+
+    fn line_number_space(&self) -> usize {
+        if !self.line_numbers {
+            return 0;
+        }
+        format!("{}", self.pane_rows() + self.buffer().row_offset).len()
+            + Editor::line_number_sep_len()
+    }
+
+    fn set_window_size(&mut self, rows: usize, cols: usize) {
+        self.screen_rows = rows.saturating_sub(RED_STATUS_HEIGHT);
+        self.screen_cols = cols;
+        self.editor_cols =
+            self.pane_cols().saturating_sub(self.line_number_space());
+    }
+
+    fn is_too_small(&self) -> bool {
+        self.pane_rows() < RED_MIN_SCREEN_ROWS
+            || self.pane_cols() < RED_MIN_SCREEN_COLS
+    }
+
+    fn scroll(&mut self) {
+        self.render_x = 0;
+        let tab_stop = self.tab_width();
+        let buffer = self.buffer_mut();
+        if let Some(row) = buffer.rows.get(buffer.cursor_y) {
+            // NOTE: text typed in front of a combining mark would take it
+            // from its base character
+            while row
+                .line
+                .get(buffer.cursor_x)
+                .is_some_and(|&c| is_combining(c))
+            {
+                buffer.cursor_x += 1;
+            }
+            let render_x =
+                editor_row_cursor_to_render(row, buffer.cursor_x, tab_stop);
+            self.render_x = render_x;
+        }
+
+        if self.buffer().cursor_y < self.buffer().row_offset {
+            self.buffer_mut().row_offset = self.buffer().cursor_y;
+        }
+        if self.buffer().cursor_y >= self.buffer().row_offset + self.pane_rows()
+        {
+            self.buffer_mut().row_offset =
+                self.buffer().cursor_y - self.pane_rows() + 1;
+        }
+
+        self.editor_cols =
+            self.pane_cols().saturating_sub(self.line_number_space());
+
+        if self.wrap_lines {
+            self.buffer_mut().col_offset = 0;
+            // NOTE: drop rows from the top until the cursor's line fits
+            while self.buffer().row_offset < self.buffer().cursor_y
+                && self.cursor_screen_position().0 >= self.pane_rows()
+            {
+                self.buffer_mut().row_offset += 1;
+            }
+            return;
+        }
+
+        if self.col_offset_locked {
+            // NOTE: Sticky scrolling keeps the visible columns during vertical
+            // motion, even if the cursor ends up outside of them.
+            return;
+        }
+
+        if self.render_x >= self.buffer().col_offset + self.editor_cols {
+            self.buffer_mut().col_offset = self.render_x - self.editor_cols + 1;
+        }
+        if self.render_x < self.buffer().col_offset {
+            self.buffer_mut().col_offset = self.render_x;
+        }
+    }
+
+    // NOTE: repeated presses cycle the cursor line through center, top and
+    // bottom of the window
+    fn recenter(&mut self, count: usize) {
+        self.buffer_mut().row_offset = match count % 3 {
+            0 => self.buffer().cursor_y.saturating_sub(self.pane_rows() / 2),
+            1 => self.buffer().cursor_y,
+            _ => self
+                .buffer()
+                .cursor_y
+                .saturating_sub(self.pane_rows().saturating_sub(1)),
+        };
+        self.recenter_count = count + 1;
+        // NOTE: also repaint everything, in case something else wrote to the
+        // terminal
+        self.drawn_rows.clear();
+    }
+
+    fn position_less(pos1: &(usize, usize), pos2: &(usize, usize)) -> bool {
+        let ((x1, y1), (x2, y2)) = (pos1, pos2);
+
+        y1 < y2 || y1 == y2 && x1 < x2
+    }
+
+    // NOTE: wide glyphs that don't fit move to the next line, a full last
+    // line gets an empty one after it for the cursor
+    fn wrap_starts(&self, row: &Row) -> Vec<usize> {
+        let mut starts = vec![0];
+        if self.editor_cols == 0 {
+            return starts;
+        }
+
+        for (column, c) in render_glyphs(&row.render) {
+            let start = starts[starts.len() - 1];
+            if column + char_width(c) > start + self.editor_cols {
+                starts.push(column);
+            }
+        }
+        if row.render.len() == starts[starts.len() - 1] + self.editor_cols {
+            starts.push(row.render.len());
+        }
+
+        starts
+    }
+
+    // NOTE: every screen line shows a file row starting at a render column
+    fn screen_lines(&self) -> Vec<Position> {
+        let mut lines = Vec::with_capacity(self.pane_rows());
+        let mut filerow = self.buffer().row_offset;
+
+        while lines.len() < self.pane_rows() {
+            match self.buffer().rows.get(filerow) {
+                Some(row) if self.wrap_lines => lines.extend(
+                    self.wrap_starts(row)
+                        .into_iter()
+                        .map(|start| (filerow, start)),
+                ),
+                _ => lines.push((filerow, self.buffer().col_offset)),
+            }
+            filerow += 1;
+        }
+        lines.truncate(self.pane_rows());
+
+        lines
+    }
+
+    fn cursor_screen_position(&self) -> Position {
+        if !self.wrap_lines {
+            let col = self
+                .render_x
+                .saturating_sub(self.buffer().col_offset)
+                .min(self.editor_cols.saturating_sub(1));
+            return (self.buffer().cursor_y - self.buffer().row_offset, col);
+        }
+
+        let above: usize = self.buffer().rows
+            [self.buffer().row_offset..self.buffer().cursor_y]
+            .iter()
+            .map(|row| self.wrap_starts(row).len())
+            .sum();
+        let starts = match self.buffer().rows.get(self.buffer().cursor_y) {
+            Some(row) => self.wrap_starts(row),
+            None => vec![0],
+        };
+        let line = starts
+            .iter()
+            .rposition(|&s| s <= self.render_x)
+            .unwrap_or(0);
+        (above + line, self.render_x - starts[line])
+    }
+
+    fn draw_rows(&self, dest: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        let bracket = self.matching_bracket_render_pos();
+        for (y, line) in self.screen_lines().into_iter().enumerate() {
+            self.draw_row(dest, y, line, bracket)?;
+            dest.write_all(ESC_SEQ_CLEAR_LINE)?;
+            dest.write_all(b"\r\n")?;
+        }
+
+        Ok(())
+    }
+
+    // NOTE: lines that didn't change since the last frame aren't sent again,
+    // the cursor just moves over them
+    fn draw_changed_rows(
+        &mut self,
+        dest: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let bracket = self.matching_bracket_render_pos();
+        let lines = self.screen_lines();
+        self.drawn_rows.resize(lines.len(), vec![]);
+
+        for (y, line) in lines.into_iter().enumerate() {
+            let mut row = vec![];
+            self.draw_row(&mut row, y, line, bracket)?;
+            row.extend_from_slice(ESC_SEQ_CLEAR_LINE);
+            if self.drawn_rows[y] != row {
+                dest.write_all(&row)?;
+                self.drawn_rows[y] = row;
+            }
+            dest.write_all(b"\r\n")?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_line_number(
+        &self,
+        dest: &mut impl Write,
+        filerow: usize,
+        start: usize,
+        left_padding: usize,
+    ) -> io::Result<()> {
+        let width = left_padding - Editor::line_number_sep_len();
+        if self.wrap_lines && start > 0 {
+            dest.write_all(" ".repeat(width).as_bytes())?;
+        } else {
+            if filerow == self.buffer().cursor_y {
+                dest.write_all(ESC_SEQ_INVERT_COLORS)?;
+            }
+            dest.write_all(
+                format!("{:>width$}", filerow + 1, width = width).as_bytes(),
+            )?;
+            if filerow == self.buffer().cursor_y {
+                dest.write_all(ESC_SEQ_RESET_ALL)?;
+            }
+        }
+        dest.write_all(RED_LINE_SEP.as_bytes())
+    }
+
+    fn draw_row(
+        &self,
+        dest: &mut impl Write,
+        y: usize,
+        (filerow, start): Position,
+        bracket: Option<Position>,
+    ) -> Result<(), Box<dyn Error>> {
+        let left_padding = self.line_number_space();
+        if filerow >= self.buffer().rows.len() {
+            let tilde: &[u8] = if self.show_tildes { b"~" } else { b"" };
+            if self.buffer().rows.is_empty()
+                && y == self.pane_rows() / 3
+                && !self.greeting.is_empty()
+            {
+                let welcome_msg = self
+                    .greeting
+                    .chars()
+                    .take(self.editor_cols)
+                    .collect::<String>();
+
+                let mut padding =
+                    (self.editor_cols - welcome_msg.chars().count()) / 2;
+                if padding > 0 && self.show_tildes {
+                    dest.write_all(tilde)?;
+                    padding -= 1;
+                }
+
+                while padding > 0 {
+                    dest.write_all(b" ")?;
+                    padding -= 1;
+                }
+
+                dest.write_all(&welcome_msg.into_bytes())?;
+            } else {
+                dest.write_all(tilde)?;
+            }
+        } else {
+            // NOTE: Ensure that only the first screen_cols glyphs of the
+            // line are printed!
+            let mut prev_color: Option<&Highlight> = None;
+            if left_padding > 0 {
+                self.draw_line_number(dest, filerow, start, left_padding)?;
+            }
+
+            let selection = self.selection().map(|(begin, end)| {
+                (self.render_position(begin), self.render_position(end))
+            });
+            let mut prev_background = ESC_SEQ_COLOR_DEFAULT_BG;
 
-    Ok(())
-}
+            let row = &self.buffer().rows[filerow];
+            let end = start + self.editor_cols;
+            let tabs = match self.show_tabs {
+                true => self.tab_columns(row),
+                false => vec![],
+            };
+            let mut filled = start;
+            for (column, c) in render_glyphs(&row.render) {
+                let width = char_width(c);
+                if column + width <= start {
+                    continue;
+                } else if column >= end {
+                    break;
+                }
+                let hl = &row.highlights[column];
+                filled = (column + width).min(end);
 
-impl<'i, 'o> Editor<'i, 'o> {
-    fn line_number_sep_len() -> usize {
-        RED_LINE_SEP.chars().count()
-    }
+                let background = self.background((column, filerow), selection);
+                if prev_background != background {
+                    dest.write_all(background)?;
+                    prev_background = background;
+                }
+                if column < start || column + width > end {
+                    // NOTE: wide glyphs cut off at the edges become blanks
+                    let cells = (column + width).min(end) - column.max(start);
+                    dest.write_all(" ".repeat(cells).as_bytes())?;
+                } else if tabs.binary_search(&column).is_ok() {
+                    dest.write_all(ESC_SEQ_DIM)?;
+                    dest.write_all(RED_TAB_MARKER.as_bytes())?;
+                    dest.write_all(ESC_SEQ_NORMAL_INTENSITY)?;
+                    if let Some(prev_hl) = prev_color {
+                        dest.write_all(prev_hl.color(&self.theme))?;
+                    }
+                } else if c.is_ascii_control() {
+                    let char_code = c as u8;
+                    let sym = if char_code <= 26 {
+                        b'@' + char_code
+                    } else {
+                        b'?'
+                    };
+                    dest.write_all(ESC_SEQ_INVERT_COLORS)?;
+                    dest.write_all(&[sym])?;
+                    dest.write_all(ESC_SEQ_RESET_ALL)?;
+                    prev_background = ESC_SEQ_COLOR_DEFAULT_BG;
+                    if let Some(prev_hl) = prev_color {
+                        dest.write_all(prev_hl.color(&self.theme))?;
+                    }
+                } else {
+                    let hl = if bracket == Some((column, filerow)) {
+                        &Highlight::MatchingBracket
+                    } else {
+                        hl
+                    };
+                    let current_color = Some(hl);
+                    if prev_color != current_color {
+                        dest.write_all(hl.color(&self.theme))?;
+                        prev_color = current_color;
+                    }
+                    row.write_glyph(dest, column, c)?;
+                }
+            }
+            if self.cursor_line && filerow == self.buffer().cursor_y {
+                // NOTE: the highlight reaches the right edge of the pane
+                dest.write_all(&self.theme.cursor_line)?;
+                dest.write_all(" ".repeat(end - filled).as_bytes())?;
+            }
+            dest.write_all(Highlight::Normal.color(&self.theme))?;
+            dest.write_all(ESC_SEQ_COLOR_DEFAULT_BG)?;
+        }
 
-    fn line_number_space(&self) -> usize {
-        format!("{}", self.screen_rows + self.row_offset).len()
-            + Editor::line_number_sep_len()
+        Ok(())
     }
 
-    fn scroll(&mut self) {
-        self.render_x = 0;
-        if let Some(row) = self.rows.get(self.cursor_y) {
-            self.render_x = editor_row_cursor_to_render(row, self.cursor_x);
-        }
+    fn tab_columns(&self, row: &Row) -> Vec<usize> {
+        let tab_stop = self.tab_width();
+        let mut columns = vec![];
+        let mut render_x = 0;
 
-        if self.cursor_y < self.row_offset {
-            self.row_offset = self.cursor_y;
-        }
-        if self.cursor_y >= self.row_offset + self.screen_rows {
-            self.row_offset = self.cursor_y - self.screen_rows + 1;
+        for &c in &row.line {
+            if c == '\t' {
+                columns.push(render_x);
+                render_x += tab_stop - render_x % tab_stop;
+            } else {
+                render_x += char_width(c);
+            }
         }
 
-        self.editor_cols = self.screen_cols - self.line_number_space();
+        columns
+    }
 
-        if self.render_x >= self.col_offset + self.editor_cols {
-            self.col_offset = self.render_x - self.editor_cols + 1;
-        }
-        if self.render_x < self.col_offset {
-            self.col_offset = self.render_x;
+    fn background(
+        &self,
+        pos: Position,
+        selection: Option<(Position, Position)>,
+    ) -> &[u8] {
+        let selected = selection.is_some_and(|(begin, end)| {
+            !Editor::position_less(&pos, &begin)
+                && Editor::position_less(&pos, &end)
+        });
+
+        if selected {
+            ESC_SEQ_COLOR_GRAY_BG
+        } else if self.config.line_length_limit.is_some_and(|l| pos.0 >= l) {
+            ESC_SEQ_COLOR_RED_BG
+        } else if self.cursor_line && pos.1 == self.buffer().cursor_y {
+            &self.theme.cursor_line
+        } else {
+            ESC_SEQ_COLOR_DEFAULT_BG
         }
     }
 
-    fn position_less(pos1: &(usize, usize), pos2: &(usize, usize)) -> bool {
-        let ((x1, y1), (x2, y2)) = (pos1, pos2);
+    // NOTE: the cursor is pulled along to stay on screen, so scroll keeps the
+    // new row_offset
+    fn scroll_view(&mut self, up: bool) {
+        let max_offset =
+            self.buffer().rows.len().saturating_sub(self.pane_rows());
+        self.buffer_mut().row_offset = match up {
+            true => self.buffer().row_offset.saturating_sub(RED_WHEEL_LINES),
+            false => {
+                (self.buffer().row_offset + RED_WHEEL_LINES).min(max_offset)
+            }
+        };
 
-        y1 < y2 || y1 == y2 && x1 < x2
+        let last_visible = self
+            .screen_lines()
+            .last()
+            .map_or(self.buffer().row_offset, |&(filerow, _)| filerow);
+        self.buffer_mut().cursor_y = self
+            .buffer()
+            .cursor_y
+            .clamp(self.buffer().row_offset, last_visible)
+            .min(self.buffer().rows.len());
+        let len = self
+            .buffer()
+            .rows
+            .get(self.buffer().cursor_y)
+            .map_or(0, |r| r.line.len());
+        self.buffer_mut().cursor_x = self.buffer().cursor_x.min(len);
     }
 
-    fn draw_rows(&self, dest: &mut impl Write) -> Result<(), Box<dyn Error>> {
-        let left_padding = self.line_number_space();
-        for y in 0..self.screen_rows {
-            let filerow = y + self.row_offset;
-            if filerow >= self.rows.len() {
-                if self.rows.is_empty() && y == self.screen_rows / 3 {
-                    let mut welcome_msg =
-                        format!("red editor -- version {}", RED_VERSION);
-                    welcome_msg.truncate(self.editor_cols);
-
-                    let mut padding =
-                        (self.editor_cols - welcome_msg.len()) / 2;
-                    if padding > 0 {
-                        dest.write_all(b"~")?;
-                        padding -= 1;
-                    }
-
-                    while padding > 0 {
-                        dest.write_all(b" ")?;
-                        padding -= 1;
-                    }
+    fn click(&mut self, (x, y): Position, pressed: bool, drag: bool) {
+        if y >= self.screen_rows {
+            return;
+        }
+        if !self.in_pane((x, y)) {
+            // NOTE: clicks on the separators don't change the focus
+            if !pressed || drag || !self.in_other_pane((x, y)) {
+                return;
+            }
+            self.focus_other_pane();
+            self.focus_other_view();
+        }
 
-                    dest.write_all(&welcome_msg.into_bytes())?;
-                } else {
-                    dest.write_all(b"~")?;
-                }
-            } else {
-                // NOTE: Ensure that only the first screen_cols glyphs of the
-                // line are printed!
-                let mut prev_color: Option<&Highlight> = None;
-                if filerow == self.cursor_y {
-                    dest.write_all(ESC_SEQ_INVERT_COLORS)?;
-                }
-                dest.write_all(
-                    format!(
-                        "{:>width$}",
-                        filerow + 1,
-                        width = left_padding - Editor::line_number_sep_len(),
-                    )
-                    .as_bytes(),
-                )?;
-                if filerow == self.cursor_y {
-                    dest.write_all(ESC_SEQ_RESET_ALL)?;
+        let (filerow, start) = self.screen_lines()[y - self.pane_top()];
+        self.buffer_mut().cursor_y = filerow.min(self.buffer().rows.len());
+        self.buffer_mut().cursor_x =
+            match self.buffer().rows.get(self.buffer().cursor_y) {
+                Some(row) => {
+                    let render_x = (x - self.pane_left())
+                        .saturating_sub(self.line_number_space())
+                        + start;
+                    editor_row_render_to_cursor(row, render_x, self.tab_width())
                 }
-                dest.write_all(RED_LINE_SEP.as_bytes())?;
+                None => 0,
+            };
 
-                let selection = self.selection();
+        let cursor = (self.buffer().cursor_x, self.buffer().cursor_y);
+        if pressed && !drag {
+            self.buffer_mut().mark = Some(cursor);
+        } else if !pressed && self.buffer().mark == Some(cursor) {
+            self.buffer_mut().mark = None;
+        }
+    }
 
-                for ((column, c), hl) in self.rows[filerow]
-                    .render
-                    .iter()
-                    .enumerate()
-                    .zip(self.rows[filerow].highlights.iter())
-                    .skip(self.col_offset)
-                    .take(self.editor_cols)
-                {
-                    if let Some(((begin_x, begin_y), (end_x, end_y))) =
-                        selection
-                    {
-                        if end_x <= column && end_y == filerow
-                            || end_y < filerow
-                        {
-                            dest.write_all(ESC_SEQ_COLOR_DEFAULT_BG)?;
-                        } else if column >= begin_x && filerow == begin_y
-                            || filerow > begin_y
-                        {
-                            dest.write_all(ESC_SEQ_COLOR_GRAY_BG)?;
-                        }
-                    }
-                    if c.is_ascii_control() {
-                        let char_code = *c as u8;
-                        let sym = if char_code <= 26 {
-                            b'@' + char_code
-                        } else {
-                            b'?'
-                        };
-                        dest.write_all(ESC_SEQ_INVERT_COLORS)?;
-                        dest.write_all(&[sym])?;
-                        dest.write_all(ESC_SEQ_RESET_ALL)?;
-                        if let Some(prev_hl) = prev_color {
-                            dest.write_all(prev_hl.color())?;
-                        }
-                    } else {
-                        let current_color = Some(hl);
-                        if prev_color != current_color {
-                            dest.write_all(hl.color())?;
-                            prev_color = current_color;
-                        }
-                        dest.write_all(&c.to_string().into_bytes())?;
-                    }
-                }
-                dest.write_all(ESC_SEQ_COLOR_DEFAULT)?;
-                dest.write_all(ESC_SEQ_COLOR_DEFAULT_BG)?;
+    fn in_pane(&self, (x, y): Position) -> bool {
+        (self.pane_left()..self.pane_left() + self.pane_cols()).contains(&x)
+            && (self.pane_top()..self.pane_top() + self.pane_rows())
+                .contains(&y)
+    }
+
+    // NOTE: only the sides are flipped, so the panes keep their state
+    fn in_other_pane(&mut self, pos: Position) -> bool {
+        let flip = |editor: &mut Editor| {
+            if let Some(split) = &mut editor.split {
+                split.focus_left = !split.focus_left;
             }
-            dest.write_all(ESC_SEQ_CLEAR_LINE)?;
-            dest.write_all(b"\r\n")?;
+            if let Some(hsplit) = &mut editor.hsplit {
+                hsplit.focus_top = !hsplit.focus_top;
+            }
+        };
+        if self.split.is_none() && self.hsplit.is_none() {
+            return false;
         }
+        flip(self);
+        let inside = self.in_pane(pos);
+        flip(self);
+        inside
+    }
 
-        Ok(())
+    fn render_position(&self, (x, y): Position) -> Position {
+        match self.buffer().rows.get(y) {
+            Some(row) => {
+                (editor_row_cursor_to_render(row, x, self.tab_width()), y)
+            }
+            None => (x, y),
+        }
     }
 
     fn selection(&self) -> Option<(Position, Position)> {
-        match self.mark {
+        match self.buffer().mark {
             Some(mark) => {
-                let cursor_pos = (self.cursor_x, self.cursor_y);
+                let cursor_pos =
+                    (self.buffer().cursor_x, self.buffer().cursor_y);
                 if Editor::position_less(&mark, &cursor_pos) {
                     Some((mark, cursor_pos))
                 } else {
@@ -1464,32 +4478,98 @@ impl<'i, 'o> Editor<'i, 'o> {
     fn draw_status_bar(
         &self,
         dest: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        self.draw_status_line(dest)?;
+        dest.write_all(b"\r\n")?;
+
+        Ok(())
+    }
+
+    fn draw_status_line(
+        &self,
+        dest: &mut impl Write,
     ) -> Result<(), Box<dyn Error>> {
         dest.write_all(ESC_SEQ_INVERT_COLORS)?;
 
-        let file_name = match &self.file {
+        let file_name = match &self.buffer().file {
             Some(path) => path.to_string_lossy().to_string(),
             None => "[No Name]".to_string(),
         };
 
-        let status_left = format!(
-            "{:.20} - {} lines {}",
-            file_name,
-            self.rows.len(),
-            if self.dirty { "(modified)" } else { "" }
+        let blank_lines = self.trailing_blank_lines();
+        let status_info = format!(
+            "{}{} - {} lines {}{}",
+            if self.read_only { " [RO]" } else { "" },
+            match self.buffers.len() {
+                1 => String::new(),
+                count =>
+                    format!(" (buffer {}/{})", self.current_buffer + 1, count),
+            },
+            self.buffer().rows.len(),
+            if self.buffer().dirty {
+                "(modified)"
+            } else {
+                ""
+            },
+            if blank_lines > 1 {
+                format!(" ({} trailing blank lines)", blank_lines)
+            } else {
+                String::new()
+            }
         );
-        dest.write_all(status_left.as_bytes())?;
+        let status_cols = self.pane_cols();
 
-        let syntax_name = self.syntax.map(|s| s.name).unwrap_or("no ft");
+        let syntax_name =
+            self.buffer().syntax.map(|s| s.name).unwrap_or("no ft");
+        let percent = match self.buffer().rows.len() {
+            0 => 100,
+            len => ((self.buffer().cursor_y + 1) * 100 / len).min(100),
+        };
+        let position = format!(
+            "{}/{}:{} {}%",
+            self.buffer().cursor_y + 1,
+            self.buffer().rows.len(),
+            self.render_x + 1,
+            percent
+        );
         let status_right = format!(
-            "{} | {}/{}",
+            "{} | UTF-8{} {} | {} | {}",
             syntax_name,
-            self.cursor_y + 1,
-            self.rows.len()
+            if self.buffer().invalid_utf8 {
+                " (lossy)"
+            } else {
+                ""
+            },
+            self.buffer().line_ending.name(),
+            if self.soft_tabs { "spaces" } else { "tabs" },
+            position
         );
 
-        for len in status_left.len()..self.screen_cols {
-            if self.screen_cols - len == status_right.len() {
+        // NOTE: the file name is cut off first, narrow panes only get the
+        // position, which is kept visible by cutting off the rest
+        let name_cols = status_cols
+            .saturating_sub(
+                status_info.chars().count() + status_right.len() + 1,
+            )
+            .min(20);
+        let (status_left, status_right) = if name_cols > 0 {
+            let name = file_name.chars().take(name_cols).collect::<String>();
+            (name + &status_info, status_right)
+        } else {
+            let left = file_name.chars().take(20).collect::<String>();
+            let left = left + &status_info;
+            let position =
+                position.chars().take(status_cols).collect::<String>();
+            let left_cols = match left.chars().count() + position.len() {
+                len if len < status_cols => status_cols,
+                _ => status_cols.saturating_sub(position.len() + 1),
+            };
+            (left.chars().take(left_cols).collect(), position)
+        };
+        dest.write_all(status_left.as_bytes())?;
+
+        for len in status_left.chars().count()..status_cols {
+            if status_cols - len == status_right.len() {
                 dest.write_all(status_right.as_bytes())?;
                 break;
             } else {
@@ -1498,7 +4578,6 @@ impl<'i, 'o> Editor<'i, 'o> {
         }
 
         dest.write_all(ESC_SEQ_RESET_ALL)?;
-        dest.write_all(b"\r\n")?;
 
         Ok(())
     }
@@ -1508,8 +4587,11 @@ impl<'i, 'o> Editor<'i, 'o> {
         dest: &mut impl Write,
     ) -> Result<(), Box<dyn Error>> {
         dest.write_all(ESC_SEQ_CLEAR_LINE)?;
-        let mut msg = self.status_msg.clone();
-        msg.truncate(self.editor_cols);
+        let msg = self
+            .status_msg
+            .chars()
+            .take(self.screen_cols)
+            .collect::<String>();
         let now = SystemTime::now();
 
         if !msg.is_empty()
@@ -1521,20 +4603,66 @@ impl<'i, 'o> Editor<'i, 'o> {
         Ok(())
     }
 
+    fn draw_too_small(
+        &self,
+        dest: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        dest.write_all(ESC_SEQ_CLEAR_SCREEN)?;
+        dest.write_all(ESC_SEQ_RESET_CURSOR)?;
+
+        let msg = "terminal too small"
+            .chars()
+            .take(self.screen_cols)
+            .collect::<String>();
+        dest.write_all(msg.as_bytes())?;
+
+        Ok(())
+    }
+
     fn refresh_screen(&mut self) -> Result<(), Box<dyn Error>> {
         let mut buffer = vec![];
-        self.scroll();
+
+        // NOTE: the terminal may have reflowed the old content, start over
+        if self.update_window_size()? {
+            buffer.write_all(ESC_SEQ_CLEAR_SCREEN)?;
+            self.drawn_rows.clear();
+        }
+
+        if self.is_too_small() {
+            // NOTE: There is no room for the text area, just tell the user
+            // and wait for the next resize.
+            buffer.write_all(ESC_SEQ_HIDE_CURSOR)?;
+            self.draw_too_small(&mut buffer)?;
+            self.drawn_rows.clear();
+            self.stdout.write_all(&buffer)?;
+            self.stdout.flush()?;
+
+            return Ok(());
+        }
 
         buffer.write_all(ESC_SEQ_HIDE_CURSOR)?;
         buffer.write_all(ESC_SEQ_RESET_CURSOR)?;
 
-        self.draw_rows(&mut buffer)?;
-        self.draw_status_bar(&mut buffer)?;
+        if self.split.is_some() {
+            self.draw_split(&mut buffer)?;
+            self.drawn_rows.clear();
+        } else if self.hsplit.is_some() {
+            self.draw_hsplit(&mut buffer)?;
+            self.drawn_rows.clear();
+        } else {
+            self.scroll();
+            self.update_stale_rows(self.buffer().row_offset + self.screen_rows);
+            self.draw_changed_rows(&mut buffer)?;
+            self.draw_status_bar(&mut buffer)?;
+        }
         self.draw_message_bar(&mut buffer)?;
 
+        let (cursor_row, cursor_col) = self.cursor_screen_position();
         buffer.write_all(&esc_seq_move_cursor(
-            (self.cursor_y - self.row_offset) + 1,
-            (self.render_x - self.col_offset) + 1 + self.line_number_space(),
+            cursor_row.min(self.pane_rows().saturating_sub(1))
+                + 1
+                + self.pane_top(),
+            cursor_col + 1 + self.line_number_space() + self.pane_left(),
         ))?;
 
         buffer.write_all(ESC_SEQ_SHOW_CURSOR)?;
@@ -1545,6 +4673,78 @@ impl<'i, 'o> Editor<'i, 'o> {
         Ok(())
     }
 
+    fn draw_split(
+        &mut self,
+        dest: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut panes = vec![];
+
+        for _ in 0..2 {
+            self.scroll();
+            self.update_stale_rows(self.buffer().row_offset + self.screen_rows);
+            let bracket = self.matching_bracket_render_pos();
+            let mut lines = vec![];
+            for (y, screen_line) in self.screen_lines().into_iter().enumerate()
+            {
+                let mut line = vec![];
+                self.draw_row(&mut line, y, screen_line, bracket)?;
+                lines.push(line);
+            }
+            let mut status = vec![];
+            self.draw_status_line(&mut status)?;
+            lines.push(status);
+
+            panes.push((self.pane_left(), lines));
+            self.swap_panes();
+        }
+        // NOTE: restore the render state of the active pane for the cursor
+        self.scroll();
+
+        // NOTE: The left pane has to come first, since clearing the rest of
+        // its lines would also wipe out the right pane.
+        panes.sort_by_key(|(left, _)| *left);
+
+        for y in 0..=self.screen_rows {
+            for (left, lines) in &panes {
+                if *left > 0 {
+                    dest.write_all(&esc_seq_move_cursor(y + 1, *left))?;
+                    dest.write_all(RED_PANE_SEP.as_bytes())?;
+                }
+                dest.write_all(&lines[y])?;
+                dest.write_all(ESC_SEQ_CLEAR_LINE)?;
+            }
+            dest.write_all(b"\r\n")?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_hsplit(
+        &mut self,
+        dest: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let focus_top = self.hsplit.as_ref().is_some_and(|h| h.focus_top);
+        if !focus_top {
+            self.focus_other_view();
+        }
+
+        for _ in 0..2 {
+            self.scroll();
+            self.update_stale_rows(self.buffer().row_offset + self.pane_rows());
+            self.draw_rows(dest)?;
+            self.draw_status_bar(dest)?;
+            self.focus_other_view();
+        }
+
+        if !focus_top {
+            self.focus_other_view();
+        }
+        // NOTE: restore the render state of the active pane for the cursor
+        self.scroll();
+
+        Ok(())
+    }
+
     fn set_status_message(&mut self, msg: String) {
         self.status_msg = msg;
         self.status_time = SystemTime::now();
@@ -1553,7 +4753,9 @@ impl<'i, 'o> Editor<'i, 'o> {
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
         loop {
             self.refresh_screen()?;
+            self.reading_command = true;
             let key = self.read_key()?;
+            self.reading_command = false;
             if !self.process_keypress(key)? {
                 break;
             }
@@ -1576,22 +4778,337 @@ fn enable_raw_mode() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+// NOTE: put the terminal back before the panic message is printed, the
+// editor's `Drop` only runs afterwards (if at all)
+fn restore_terminal_on_panic(original_termios: Termios) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(ESC_SEQ_DISABLE_PASTE);
+        let _ = stdout.write_all(ESC_SEQ_DISABLE_MOUSE);
+        let _ = stdout.write_all(ESC_SEQ_SHOW_CURSOR);
+        let _ = clear_screen(&mut stdout);
+        let _ = termios::tcsetattr(STDIN_FILENO, TCSAFLUSH, &original_termios);
+        default_hook(info);
+    }));
+}
+
+#[derive(Default, PartialEq, Debug)]
+struct Args {
+    cat: bool,
+    follow: bool,
+    read_only: bool,
+    no_backup: bool,
+    stdin: bool,
+    stdout: bool,
+    theme: Option<&'static Theme>,
+    color: Option<ColorDepth>,
+    files: Vec<PathBuf>,
+    positions: Vec<Option<(usize, usize)>>,
+}
+
+// NOTE: splits `file:line` or `file:line:col` unless the whole argument
+// names an existing file
+fn split_file_position(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    let whole = (PathBuf::from(arg), None);
+    if whole.0.exists() {
+        return whole;
+    }
+
+    let is_number =
+        |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    // NOTE: `:0` means the first line
+    let parse = |s: &str| s.parse::<usize>().unwrap_or(usize::MAX).max(1);
+
+    match arg.rsplit_once(':') {
+        Some((rest, last)) if !rest.is_empty() && is_number(last) => {
+            match rest.rsplit_once(':') {
+                Some((file, line)) if !file.is_empty() && is_number(line) => {
+                    (PathBuf::from(file), Some((parse(line), parse(last))))
+                }
+                _ => (PathBuf::from(rest), Some((parse(last), 1))),
+            }
+        }
+        _ => whole,
+    }
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut line = None;
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--cat" => parsed.cat = true,
+                "-f" | "--follow" => parsed.follow = true,
+                "-R" | "--readonly" => parsed.read_only = true,
+                "--no-backup" => parsed.no_backup = true,
+                "-" => parsed.stdin = true,
+                "--stdout" => parsed.stdout = true,
+                "--theme" => {
+                    let name = args.next().unwrap_or_default();
+                    match find_theme(&name) {
+                        Some(theme) => parsed.theme = Some(theme),
+                        None => {
+                            return Err(format!("unknown theme `{}`", name))
+                        }
+                    }
+                }
+                color if color.starts_with("--color=") => {
+                    let depth = &color["--color=".len()..];
+                    match ColorDepth::parse(depth) {
+                        Some(depth) => parsed.color = Some(depth),
+                        None => {
+                            return Err(format!(
+                                "unknown color depth `{}`",
+                                depth
+                            ))
+                        }
+                    }
+                }
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option {}", flag))
+                }
+                // NOTE: like vi, `+LINE` applies to the following file
+                plus if plus.len() > 1
+                    && plus.starts_with('+')
+                    && plus[1..].bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    line = Some(plus[1..].parse().unwrap_or(usize::MAX));
+                }
+                file => {
+                    let (file, position) = split_file_position(file);
+                    parsed.files.push(file);
+                    parsed.positions.push(
+                        position.or(line.take().map(|line| (line.max(1), 1))),
+                    );
+                }
+            }
+        }
+
+        if parsed.follow && parsed.files.len() != 1 {
+            return Err("--follow needs exactly one file".to_string());
+        }
+        if parsed.stdin && !parsed.files.is_empty() {
+            return Err("- can't be combined with files".to_string());
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn read_piped_stdin() -> Result<Option<String>, Box<dyn Error>> {
+    if unsafe { libc::isatty(STDIN_FILENO) } == 1 {
+        return Ok(None);
+    }
+
+    let mut content = vec![];
+    io::stdin().read_to_end(&mut content)?;
+
+    // NOTE: keys are read from the terminal from now on
+    let tty = File::open("/dev/tty")?;
+    if unsafe { libc::dup2(tty.as_raw_fd(), STDIN_FILENO) } == -1 {
+        return Err(Box::new(io::Error::last_os_error()));
+    }
+
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+// NOTE: the screen is drawn on the terminal, the returned file is the
+// original stdout that gets the buffer once red quits
+fn redirect_stdout_to_tty() -> Result<File, Box<dyn Error>> {
+    let output = unsafe { libc::dup(STDOUT_FILENO) };
+    if output == -1 {
+        return Err(Box::new(io::Error::last_os_error()));
+    }
+
+    let tty = File::options().write(true).open("/dev/tty")?;
+    if unsafe { libc::dup2(tty.as_raw_fd(), STDOUT_FILENO) } == -1 {
+        return Err(Box::new(io::Error::last_os_error()));
+    }
+
+    Ok(unsafe { File::from_raw_fd(output) })
+}
+
+// NOTE: `value` is the content of RED_TABSTOP
+fn parse_tab_stop(value: Option<&str>) -> Result<usize, String> {
+    match value {
+        Some(value) => match value.parse() {
+            Ok(tab_stop @ 1..=16) => Ok(tab_stop),
+            _ => Err(format!("ignoring invalid RED_TABSTOP `{}`", value)),
+        },
+        None => Ok(RED_TAB_STOP),
+    }
+}
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// NOTE: keep the previous content of `path` in `path~`
+fn backup_file(path: &Path) -> io::Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push("~");
+    fs::copy(path, backup)?;
+    Ok(true)
+}
+
+fn cat(
+    files: &[PathBuf],
+    config: Config,
+    tab_stop: usize,
+    theme: Theme,
+) -> Result<(), Box<dyn Error>> {
+    let mut editor = Editor::headless(config);
+    editor.tab_stop = tab_stop;
+    editor.theme = theme;
+
+    // NOTE: see https://no-color.org
+    let color = !matches!(env::var_os("NO_COLOR"), Some(v) if !v.is_empty());
+    let mut stdout = BufWriter::new(io::stdout());
+
+    for file in files {
+        // NOTE: `open` treats a missing file as a new one
+        if let Err(err) = fs::metadata(file) {
+            return Err(format!("{}: {}", file.display(), err).into());
+        }
+        editor.buffer_mut().rows.clear();
+        editor.open(file)?;
+        editor.write_highlighted(&mut stdout, color)?;
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
 fn main() {
-    let mut editor = Editor::new().unwrap();
-    let args = env::args().collect::<Vec<_>>();
+    let (config, warnings) = Config::load();
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("error: {}\n{}", msg, RED_USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let (tab_stop, tab_stop_warning) =
+        match parse_tab_stop(env::var("RED_TABSTOP").ok().as_deref()) {
+            Ok(tab_stop) => (tab_stop, None),
+            Err(warning) => (RED_TAB_STOP, Some(warning)),
+        };
 
-    if let [_prog, filename] = args.as_slice() {
-        editor.open(Path::new(&filename)).expect("open failed!");
+    let theme = args
+        .theme
+        .unwrap_or(&THEMES[0])
+        .with_colors(&config.colors)
+        .with_depth(args.color.unwrap_or_else(ColorDepth::from_env));
+
+    if args.cat {
+        if let Err(e) = cat(&args.files, config, tab_stop, theme) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let piped = if args.files.is_empty() {
+        read_piped_stdin().unwrap_or_else(|e| {
+            eprintln!("error: can't read stdin: {}", e);
+            std::process::exit(1);
+        })
+    } else {
+        None
+    };
+
+    let output = if args.stdout {
+        Some(redirect_stdout_to_tty().unwrap_or_else(|e| {
+            eprintln!("error: can't open the terminal: {}", e);
+            std::process::exit(1);
+        }))
+    } else {
+        None
+    };
+
+    let mut editor = Editor::new(config).unwrap();
+    if let Some(original_termios) = editor.original_termios {
+        restore_terminal_on_panic(original_termios);
+    }
+    editor.tab_stop = tab_stop;
+    editor.theme = theme;
+    editor.read_only = args.read_only;
+    // NOTE: RED_BACKUP is still honored, an empty value turns backups off
+    editor.backup = !args.no_backup
+        && !matches!(env::var_os("RED_BACKUP"), Some(v) if v.is_empty());
+    editor.positions = red_positions::path();
+
+    match (args.files.as_slice(), args.positions.as_slice()) {
+        ([filename], [position]) => {
+            editor.open(filename).expect("open failed!");
+            if let Some(position) = position {
+                editor.goto_position(*position);
+            }
+        }
+        ([left, right], [left_position, right_position]) => {
+            editor.open(left).expect("open failed!");
+            editor.open_buffer(right).expect("open failed!");
+            if let Some(position) = right_position {
+                editor.goto_position(*position);
+            }
+            editor.switch_buffer(0);
+            if let Some(position) = left_position {
+                editor.goto_position(*position);
+            }
+            editor.split_window(1);
+        }
+        _ => (),
+    }
+
+    if let Some(text) = piped {
+        editor.load_text(&text);
+    }
+
+    if args.follow {
+        editor.start_follow().expect("follow failed!");
     }
 
-    set_status_message!(
-        &mut editor,
-        "HELP: C-s = save | C-q = quit | C-f = find | C-SPC = select"
-    );
+    if let Some(warning) = warnings.first() {
+        let more = match warnings.len() {
+            1 => String::new(),
+            n => format!(" (+{} more)", n - 1),
+        };
+        set_status_message!(&mut editor, "config: {}{}", warning, more);
+    } else if let Some(warning) = tab_stop_warning {
+        set_status_message!(&mut editor, "{}", warning);
+    } else if editor.buffer().invalid_utf8 {
+        set_status_message!(
+            &mut editor,
+            "WARNING: invalid UTF-8 was replaced, saving will keep it replaced"
+        );
+    } else {
+        set_status_message!(
+            &mut editor,
+            "HELP: C-s = save | C-q = quit | C-f = find | C-SPC = select"
+        );
+    }
 
     if let Err(e) = editor.run() {
         clear_screen(&mut io::stdout()).unwrap();
-        eprintln!("error: {}", e)
+        eprintln!("error: {}", e);
+        return;
+    }
+
+    if let Some(mut output) = output {
+        let mut content = vec![];
+        editor.write_rows(&mut content).expect("write failed!");
+        // NOTE: leave raw mode before anything else is printed
+        drop(editor);
+        output.write_all(&content).expect("write failed!");
     }
 }
 
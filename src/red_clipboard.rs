@@ -0,0 +1,127 @@
+use std::env;
+use std::ops::Range;
+
+const BASE64: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// NOTE: many terminals drop OSC 52 sequences larger than this
+pub const OSC52_MAX_LEN: usize = 100_000;
+
+// NOTE: screen limits the length of a DCS passthrough string
+const SCREEN_CHUNK_LEN: usize = 76;
+
+pub const OSC52_REQUEST: &[u8] = b"\x1b]52;c;?\x07";
+
+pub fn base64_encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                let sextet = (n >> (18 - 6 * idx)) & 0x3f;
+                output.push(BASE64[sextet as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=').as_bytes();
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut n = 0u32;
+
+    for (idx, &c) in input.iter().enumerate() {
+        let sextet = BASE64.iter().position(|&b| b == c)? as u32;
+        n = n << 6 | sextet;
+
+        if idx % 4 == 3 {
+            output.extend_from_slice(&n.to_be_bytes()[1..]);
+            n = 0;
+        }
+    }
+
+    match input.len() % 4 {
+        0 => (),
+        2 => output.push((n >> 4) as u8),
+        3 => output.extend_from_slice(&(n >> 2).to_be_bytes()[2..]),
+        _ => return None,
+    }
+
+    Some(output)
+}
+
+pub fn terminal() -> String {
+    if env::var_os("TMUX").is_some() {
+        "tmux".to_string()
+    } else {
+        env::var("TERM").unwrap_or_default()
+    }
+}
+
+// NOTE: only for copying, terminals that don't know OSC 52 ignore it. Reading
+// the clipboard has to be enabled with `clipboard_read`.
+pub fn osc52_supported(term: &str) -> bool {
+    !matches!(term, "" | "dumb" | "linux")
+}
+
+pub fn osc52_copy(text: &str, term: &str) -> Option<Vec<u8>> {
+    let encoded = base64_encode(text.as_bytes());
+    if encoded.len() > OSC52_MAX_LEN {
+        return None;
+    }
+
+    let osc = format!("\x1b]52;c;{}\x07", encoded);
+    let sequence = if term.starts_with("screen") {
+        osc.as_bytes()
+            .chunks(SCREEN_CHUNK_LEN)
+            .flat_map(|chunk| [&b"\x1bP"[..], chunk, &b"\x1b\\"[..]].concat())
+            .collect()
+    } else if term.starts_with("tmux") {
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+            .into_bytes()
+    } else {
+        osc.into_bytes()
+    };
+
+    Some(sequence)
+}
+
+// NOTE: keys typed while waiting for the reply end up around it
+pub fn osc52_reply_range(input: &[u8]) -> Option<Range<usize>> {
+    let start = input.windows(5).position(|w| w == b"\x1b]52;")?;
+    let rest = &input[start..];
+    let end = rest
+        .iter()
+        .position(|&b| b == b'\x07')
+        .map(|idx| idx + 1)
+        .or_else(|| {
+            rest.windows(2)
+                .position(|w| w == b"\x1b\\")
+                .map(|idx| idx + 2)
+        })?;
+
+    Some(start..start + end)
+}
+
+pub fn osc52_parse_reply(reply: &[u8]) -> Option<String> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let start = reply.find("\x1b]52;")? + "\x1b]52;".len();
+    let reply = &reply[start..];
+    let payload = &reply[reply.find(';')? + 1..];
+    let payload = payload
+        .strip_suffix('\x07')
+        .or_else(|| payload.strip_suffix("\x1b\\"))?;
+
+    String::from_utf8(base64_decode(payload)?).ok()
+}
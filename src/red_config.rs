@@ -0,0 +1,309 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::languages::{find_syntax, Syntax};
+
+pub const COLOR_KEYS: &[&str] = &[
+    "normal",
+    "string",
+    "number",
+    "match",
+    "matching_bracket",
+    "comment",
+    "keyword",
+    "type",
+    "builtin",
+    "heading",
+];
+
+pub struct Config {
+    pub filetypes: Vec<(String, &'static Syntax)>,
+    // NOTE: SGR parameters like `33` or `38;2;255;0;255` for a key of
+    // COLOR_KEYS
+    pub colors: Vec<(String, String)>,
+    pub sticky_scroll: bool,
+    pub line_length_limit: Option<usize>,
+    pub undo_limit: usize,
+    pub soft_tabs: bool,
+    pub system_clipboard: bool,
+    pub clipboard_read: bool,
+    pub auto_indent: bool,
+    pub strip_trailing_whitespace: bool,
+    pub ensure_final_newline: bool,
+    pub mouse: bool,
+    pub wrap_lines: bool,
+    pub show_tabs: bool,
+    pub show_tildes: bool,
+    pub line_numbers: bool,
+    pub cursor_line: bool,
+    pub greeting: Option<String>,
+    pub autosave_secs: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            filetypes: vec![],
+            colors: vec![],
+            sticky_scroll: false,
+            line_length_limit: None,
+            undo_limit: 1000,
+            soft_tabs: false,
+            system_clipboard: true,
+            clipboard_read: false,
+            auto_indent: true,
+            strip_trailing_whitespace: false,
+            ensure_final_newline: false,
+            mouse: true,
+            wrap_lines: false,
+            show_tabs: false,
+            show_tildes: true,
+            line_numbers: true,
+            cursor_line: false,
+            greeting: None,
+            autosave_secs: None,
+        }
+    }
+}
+
+enum Section {
+    General,
+    Filetypes,
+    Colors,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("RED_CONFIG").map(PathBuf::from).or_else(|| {
+            env::var_os("HOME").map(|h| PathBuf::from(h).join(".redrc"))
+        })
+    }
+
+    pub fn load() -> (Config, Vec<String>) {
+        match Config::path().map(fs::read_to_string) {
+            Some(Ok(content)) => Config::parse(&content),
+            _ => (Config::default(), vec![]),
+        }
+    }
+
+    pub fn parse(content: &str) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut warnings = vec![];
+        let mut section = Section::General;
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = match &line[1..line.len() - 1] {
+                    "filetypes" => Section::Filetypes,
+                    "colors" => Section::Colors,
+                    name => {
+                        warnings.push(format!(
+                            "line {}: unknown section `{}`",
+                            number + 1,
+                            name
+                        ));
+                        Section::General
+                    }
+                };
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    warnings.push(format!(
+                        "line {}: expected `key = value`",
+                        number + 1
+                    ));
+                    continue;
+                }
+            };
+
+            if let Err(msg) = config.set(&section, key, value) {
+                warnings.push(format!("line {}: {}", number + 1, msg));
+            }
+        }
+
+        (config, warnings)
+    }
+
+    fn set(
+        &mut self,
+        section: &Section,
+        key: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        match section {
+            Section::General => match key {
+                "sticky_scroll" => {
+                    self.sticky_scroll = parse_bool(value)?;
+                    Ok(())
+                }
+                "line_length_limit" => {
+                    self.line_length_limit = match parse_number(value)? {
+                        0 => None,
+                        limit => Some(limit),
+                    };
+                    Ok(())
+                }
+                "soft_tabs" => {
+                    self.soft_tabs = parse_bool(value)?;
+                    Ok(())
+                }
+                "clipboard_read" => {
+                    self.clipboard_read = parse_bool(value)?;
+                    Ok(())
+                }
+                "system_clipboard" => {
+                    self.system_clipboard = parse_bool(value)?;
+                    Ok(())
+                }
+                "auto_indent" => {
+                    self.auto_indent = parse_bool(value)?;
+                    Ok(())
+                }
+                "strip_trailing_whitespace" => {
+                    self.strip_trailing_whitespace = parse_bool(value)?;
+                    Ok(())
+                }
+                "ensure_final_newline" => {
+                    self.ensure_final_newline = parse_bool(value)?;
+                    Ok(())
+                }
+                "mouse" => {
+                    self.mouse = parse_bool(value)?;
+                    Ok(())
+                }
+                "wrap_lines" => {
+                    self.wrap_lines = parse_bool(value)?;
+                    Ok(())
+                }
+                "show_tabs" => {
+                    self.show_tabs = parse_bool(value)?;
+                    Ok(())
+                }
+                "show_tildes" => {
+                    self.show_tildes = parse_bool(value)?;
+                    Ok(())
+                }
+                "line_numbers" => {
+                    self.line_numbers = parse_bool(value)?;
+                    Ok(())
+                }
+                "cursor_line" => {
+                    self.cursor_line = parse_bool(value)?;
+                    Ok(())
+                }
+                "greeting" => {
+                    self.greeting = Some(value.to_owned());
+                    Ok(())
+                }
+                "autosave_secs" => {
+                    self.autosave_secs = match parse_number(value)? {
+                        0 => None,
+                        secs => Some(secs as u64),
+                    };
+                    Ok(())
+                }
+                "undo_limit" => {
+                    self.undo_limit = parse_number(value)?;
+                    Ok(())
+                }
+                // NOTE: colors may also be set without the [colors] header
+                key if COLOR_KEYS.contains(&key) => {
+                    self.colors.push((key.to_owned(), parse_color(value)?));
+                    Ok(())
+                }
+                _ => Err(format!("unknown key `{}`", key)),
+            },
+            Section::Filetypes => match find_syntax(value) {
+                Some(syntax) => {
+                    self.filetypes.push((key.to_owned(), syntax));
+                    Ok(())
+                }
+                None => Err(format!("unknown filetype `{}`", value)),
+            },
+            Section::Colors if COLOR_KEYS.contains(&key) => {
+                self.colors.push((key.to_owned(), parse_color(value)?));
+                Ok(())
+            }
+            Section::Colors => Err(format!("unknown color `{}`", key)),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected a boolean, got `{}`", value)),
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, got `{}`", value))
+}
+
+// NOTE: `#rrggbb` is a truecolor, anything else is used as SGR parameters
+fn parse_color(value: &str) -> Result<String, String> {
+    let error = || format!("expected `#rrggbb` or SGR codes, got `{}`", value);
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let rgb = u32::from_str_radix(hex, 16).map_err(|_| error())?;
+        let [_, r, g, b] = rgb.to_be_bytes();
+        return Ok(format!("38;2;{};{};{}", r, g, b));
+    }
+
+    let is_sgr = value.split(';').all(|code| {
+        !code.is_empty()
+            && code.len() <= 3
+            && code.bytes().all(|b| b.is_ascii_digit())
+    });
+    if is_sgr {
+        Ok(value.to_owned())
+    } else {
+        Err(error())
+    }
+}
+
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // NOTE: let the last '*' swallow one more character
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
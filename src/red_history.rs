@@ -0,0 +1,145 @@
+pub type Position = (usize, usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    Insert(Position, Vec<char>),
+    Delete(Position, Vec<char>),
+}
+
+pub fn text_end((x, y): Position, text: &[char]) -> Position {
+    text.iter().fold(
+        (x, y),
+        |(x, y), &c| {
+            if c == '\n' {
+                (0, y + 1)
+            } else {
+                (x + 1, y)
+            }
+        },
+    )
+}
+
+impl Edit {
+    // NOTE: returns the edit back if it can't be merged into self
+    fn merge(&mut self, edit: Edit) -> Option<Edit> {
+        match (self, edit) {
+            (Edit::Insert(at, text), Edit::Insert(pos, more))
+                if pos == text_end(*at, text) =>
+            {
+                text.extend(more);
+                None
+            }
+            (Edit::Delete(at, text), Edit::Delete(pos, mut more))
+                if text_end(pos, &more) == *at =>
+            {
+                more.append(text);
+                *text = more;
+                *at = pos;
+                None
+            }
+            (Edit::Delete(at, text), Edit::Delete(pos, more)) if pos == *at => {
+                text.extend(more);
+                None
+            }
+            (_, edit) => Some(edit),
+        }
+    }
+}
+
+pub struct EditAction {
+    pub edits: Vec<Edit>,
+    pub cursor: Position,
+}
+
+pub struct History {
+    undo: Vec<EditAction>,
+    redo: Vec<EditAction>,
+    open: bool,
+    typing: bool,
+    saved: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            undo: vec![],
+            redo: vec![],
+            open: false,
+            typing: false,
+            saved: Some(0),
+        }
+    }
+}
+
+impl History {
+    pub fn start_keypress(&mut self, typing: bool) {
+        self.open = typing && self.typing;
+        self.typing = typing;
+    }
+
+    pub fn record(&mut self, edit: Edit, cursor: Position, limit: usize) {
+        self.redo.clear();
+        // NOTE: the saved state can't be reached anymore
+        if self.saved > Some(self.undo.len()) {
+            self.saved = None;
+        }
+
+        if self.open {
+            if let Some(action) = self.undo.last_mut() {
+                match action.edits.last_mut() {
+                    Some(last) => {
+                        if let Some(edit) = last.merge(edit) {
+                            action.edits.push(edit);
+                        }
+                    }
+                    None => action.edits.push(edit),
+                }
+                return;
+            }
+        }
+
+        self.open = true;
+        self.undo.push(EditAction {
+            edits: vec![edit],
+            cursor,
+        });
+
+        if self.undo.len() > limit {
+            let dropped = self.undo.len() - limit;
+            self.undo.drain(..dropped);
+            self.saved = self.saved.and_then(|n| n.checked_sub(dropped));
+        }
+    }
+
+    pub fn pop_undo(&mut self) -> Option<EditAction> {
+        self.open = false;
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<EditAction> {
+        self.open = false;
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, action: EditAction) {
+        self.undo.push(action);
+    }
+
+    pub fn push_redo(&mut self, action: EditAction) {
+        self.redo.push(action);
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.open = false;
+        self.typing = false;
+        self.saved = Some(self.undo.len());
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.undo.len())
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::default();
+    }
+}
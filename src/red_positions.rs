@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::red_history::Position;
+
+// NOTE: only the most recently stored files are kept
+const MAX_FILES: usize = 1000;
+
+// NOTE: opt-in, so that scripts and CI runs don't leave state behind
+pub fn path() -> Option<PathBuf> {
+    env::var_os("RED_REMEMBER_POSITIONS").filter(|value| !value.is_empty())?;
+    let cache =
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache"))
+            })?;
+
+    Some(cache.join("red").join("positions"))
+}
+
+// NOTE: every line is `LINE<TAB>COLUMN<TAB>PATH`, newest first
+fn parse(content: &str) -> impl Iterator<Item = (Position, &str)> {
+    content.lines().filter_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        let y = fields.next()?.parse().ok()?;
+        let x = fields.next()?.parse().ok()?;
+        Some(((x, y), fields.next()?))
+    })
+}
+
+pub fn lookup(positions: &Path, file: &Path) -> Option<Position> {
+    let content = fs::read_to_string(positions).ok()?;
+    let file = file.to_str()?;
+
+    let position = parse(&content)
+        .find(|&(_, other)| other == file)
+        .map(|(position, _)| position);
+    position
+}
+
+pub fn store(
+    positions: &Path,
+    file: &Path,
+    (x, y): Position,
+) -> io::Result<()> {
+    let file = match file.to_str() {
+        Some(file) if !file.contains('\n') => file,
+        _ => return Ok(()),
+    };
+
+    let content = fs::read_to_string(positions).unwrap_or_default();
+    let mut output = format!("{}\t{}\t{}\n", y, x, file);
+    for ((x, y), other) in parse(&content)
+        .filter(|&(_, other)| other != file)
+        .take(MAX_FILES - 1)
+    {
+        output.push_str(&format!("{}\t{}\t{}\n", y, x, other));
+    }
+
+    if let Some(dir) = positions.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(positions, output)
+}
@@ -1,27 +1,40 @@
 use std::error::Error;
+use std::io::Cursor;
 use std::io::Read;
 use std::io::Write;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use tempfile::NamedTempFile;
 
+use crate::editor_find_callback;
+use crate::is_combining;
 use crate::languages::SYNTAX_C;
 use crate::languages::SYNTAX_HASKELL;
+use crate::languages::SYNTAX_LISP;
+use crate::languages::SYNTAX_MARKDOWN;
 use crate::languages::SYNTAX_RUST;
+use crate::languages::SYNTAX_SHELL;
+use crate::parse_tab_stop;
 use crate::parse_utf8;
+use crate::red_clipboard::{
+    base64_decode, base64_encode, osc52_copy, osc52_parse_reply,
+    osc52_reply_range,
+};
+use crate::red_config::{glob_match, Config};
 use crate::red_error::EditorError;
+use crate::Change;
 use crate::Editor;
 use crate::EditorKey;
+use crate::Highlight;
+use crate::LineEnding;
 use crate::Row;
-use crate::SearchDirection;
 use crate::BACKSPACE;
 use crate::ESC;
 use crate::ESC_SEQ_INVERT_COLORS;
 use crate::ESC_SEQ_RESET_ALL;
-use crate::RED_QUIT_TIMES;
+use crate::RED_KILL_RING_SIZE;
 use crate::RED_STATUS_HEIGHT;
 use crate::RED_TAB_STOP;
 use crate::{editor_row_cursor_to_render, editor_row_render_to_cursor};
@@ -34,18 +47,60 @@ fn test_file(filename: &str) -> String {
 
 #[test]
 fn test_render_to_cursor() {
-    let mut row = Row::empty(0);
+    let mut row = Row::empty();
 
     row.line = "'a'".chars().collect();
-    assert_eq!(editor_row_render_to_cursor(&row, 2), 2);
+    assert_eq!(editor_row_render_to_cursor(&row, 2, RED_TAB_STOP), 2);
 
     row.line = "\t'a'".chars().collect();
-    assert_eq!(editor_row_render_to_cursor(&row, RED_TAB_STOP + 2), 3);
+    assert_eq!(
+        editor_row_render_to_cursor(&row, RED_TAB_STOP + 2, RED_TAB_STOP),
+        3
+    );
+    assert_eq!(editor_row_render_to_cursor(&row, 4 + 2, 4), 3);
+}
+
+#[test]
+fn test_tab_stop() {
+    assert_eq!(parse_tab_stop(Some("4")), Ok(4));
+    assert!(parse_tab_stop(Some("0")).is_err());
+    assert!(parse_tab_stop(Some("four")).is_err());
+    assert_eq!(parse_tab_stop(None), Ok(RED_TAB_STOP));
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.tab_stop = 4;
+    editor.append_row("a\tb".chars().collect());
+    assert_eq!(
+        editor.buffer().rows[0].render.iter().collect::<String>(),
+        "a   b"
+    );
+}
+
+#[test]
+fn test_syntax_tab_stop() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("\tx".chars().collect());
+    let render = |editor: &Editor| {
+        editor.buffer().rows[0].render.iter().collect::<String>()
+    };
+
+    editor.buffer_mut().file = Some(PathBuf::from("main.rs"));
+    editor.select_syntax_highlight();
+    assert_eq!(render(&editor), "    x");
+    editor.buffer_mut().cursor_x = 1;
+    editor.scroll();
+    assert_eq!(editor.render_x, 4);
+
+    editor.buffer_mut().file = Some(PathBuf::from("notes.txt"));
+    editor.select_syntax_highlight();
+    assert_eq!(render(&editor), "        x");
+    editor.scroll();
+    assert_eq!(editor.render_x, 8);
 }
 
 prop_compose! {
     fn line_and_idx ()
-        (s in "[ \ta-zA-ZäöüÄÖÜ:;+-/<>*()]+")
+        (s in "[ \ta-zA-ZäöüÄÖÜ中文字漢😀:;+-/<>*()\u{301}\u{308}]+")
         (index in 0..=s.chars().count(), s in Just(s)) -> (String, usize) {
       (s, index)
     }
@@ -53,12 +108,21 @@ prop_compose! {
 
 proptest! {
     #[test]
-    fn test_render_cursor_loop((line, cx) in line_and_idx()) {
-        let mut row = Row::empty(0);
+    fn test_render_cursor_loop(
+        (line, cx) in line_and_idx(),
+        tab_stop in 1..=16usize,
+    ) {
+        let mut row = Row::empty();
 
         row.line = line.chars().collect();
-        let rx = editor_row_cursor_to_render(&row, cx);
-        prop_assert_eq!(editor_row_render_to_cursor(&row, rx), cx);
+        let rx = editor_row_cursor_to_render(&row, cx, tab_stop);
+        // NOTE: a cursor in front of combining marks comes back behind them
+        let marks =
+            row.line[cx..].iter().take_while(|&&c| is_combining(c)).count();
+        prop_assert_eq!(
+            editor_row_render_to_cursor(&row, rx, tab_stop),
+            cx + marks
+        );
     }
 }
 
@@ -66,35 +130,18 @@ fn dummy_editor<'i, 'o>(
     stdin: Box<dyn Read + 'i>,
     stdout: Box<dyn Write + 'o>,
 ) -> Editor<'i, 'o> {
-    Editor {
-        original_termios: None,
-        cursor_x: 0,
-        cursor_y: 0,
-        render_x: 0,
-        screen_rows: 50 - RED_STATUS_HEIGHT,
-        screen_cols: 60,
-        editor_cols: 60,
-        row_offset: 0,
-        col_offset: 0,
-        rows: vec![],
-        file: None,
-        status_msg: String::new(),
-        status_time: SystemTime::UNIX_EPOCH,
-        dirty: false,
-        quit_times: RED_QUIT_TIMES,
-        search_dir: SearchDirection::Forward,
-        last_match: None,
-        win_changed: Arc::new(AtomicBool::new(false)),
-        stored_hl: None,
-        syntax: None,
-        mark: None,
-        clipboard: String::new(),
-        stdin,
-        stdout,
-    }
+    let mut editor = Editor::headless(Config::default());
+    editor.screen_rows = 50 - RED_STATUS_HEIGHT;
+    editor.screen_cols = 60;
+    editor.editor_cols = 60;
+    editor.window_size = || Ok((50, 60));
+    editor.stdin = stdin;
+    editor.stdout = stdout;
+    editor
 }
 
 #[test]
+#[allow(clippy::cognitive_complexity)]
 fn test_read_key() {
     let stdin = b"[Ahello world";
     let stdout = vec![];
@@ -142,8 +189,28 @@ fn test_read_key() {
     assert_eq!(editor.read_key().unwrap(), EditorKey::PageUp);
     assert_eq!(editor.read_key().unwrap(), EditorKey::PageDown);
 
-    let stdin = "äÄüÜöÖß".as_bytes();
+    let stdin = b"\x1b[1;2A\x1b[1;2B\x1b[1;2C\x1b[1;2D\x1b[1;5C\x1b[1;5D";
     editor.stdin = Box::new(&stdin[..]);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::ShiftArrowUp);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::ShiftArrowDown);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::ShiftArrowRight);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::ShiftArrowLeft);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Meta('f'));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Meta('b'));
+
+    let stdin = b"\x1b[Z";
+    editor.stdin = Box::new(&stdin[..]);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::BackTab);
+
+    let stdin = b"\x1b[1;3A\x1b[1;3B\x1b[1;4C\x1b[1;2H";
+    editor.stdin = Box::new(&stdin[..]);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Meta('p'));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Meta('n'));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::ArrowRight);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Home);
+
+    let stdin = "äÄüÜöÖß".as_bytes();
+    editor.stdin = Box::new(stdin);
     assert_eq!(editor.read_key().unwrap(), EditorKey::Other('ä'));
     assert_eq!(editor.read_key().unwrap(), EditorKey::Other('Ä'));
     assert_eq!(editor.read_key().unwrap(), EditorKey::Other('ü'));
@@ -152,11 +219,12 @@ fn test_read_key() {
     assert_eq!(editor.read_key().unwrap(), EditorKey::Other('Ö'));
     assert_eq!(editor.read_key().unwrap(), EditorKey::Other('ß'));
 
-    let stdin = b"\x01\x02\x03";
+    let stdin = b"\x01\x02\x03\x1f";
     editor.stdin = Box::new(&stdin[..]);
     assert_eq!(editor.read_key().unwrap(), EditorKey::Ctrl('a'));
     assert_eq!(editor.read_key().unwrap(), EditorKey::Ctrl('b'));
     assert_eq!(editor.read_key().unwrap(), EditorKey::Ctrl('c'));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Ctrl('/'));
 }
 
 fn send_test_string(
@@ -178,28 +246,879 @@ fn test_process_keypress_simple() {
 
     send_test_string(&mut editor, "hello").unwrap();
 
-    assert_eq!(editor.rows.len(), 1);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "hello");
+    assert_eq!(editor.buffer().rows.len(), 1);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "hello"
+    );
 
     editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
-    assert_eq!(editor.rows.len(), 2);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "hello");
+    assert_eq!(editor.buffer().rows.len(), 2);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "hello"
+    );
 
     send_test_string(&mut editor, "world").unwrap();
 
-    assert_eq!(editor.rows.len(), 2);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "hello");
-    assert_eq!(editor.rows[1].line.iter().collect::<String>(), "world");
-    assert_eq!(editor.cursor_x, 5);
-    assert_eq!(editor.cursor_y, 1);
+    assert_eq!(editor.buffer().rows.len(), 2);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "hello"
+    );
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "world"
+    );
+    assert_eq!(editor.buffer().cursor_x, 5);
+    assert_eq!(editor.buffer().cursor_y, 1);
 
     editor.process_keypress(EditorKey::Home).unwrap();
-    assert_eq!(editor.cursor_x, 0);
-    assert_eq!(editor.cursor_y, 1);
+    assert_eq!(editor.buffer().cursor_x, 0);
+    assert_eq!(editor.buffer().cursor_y, 1);
 
     send_test_string(&mut editor, "--->").unwrap();
 
-    assert_eq!(editor.rows[1].line.iter().collect::<String>(), "--->world");
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "--->world"
+    );
+}
+
+#[test]
+fn test_auto_indent() {
+    let stdin = b"";
+    let stdout = vec![];
+    let mut editor = dummy_editor(Box::new(&stdin[..]), Box::new(stdout));
+
+    send_test_string(&mut editor, "\t  if x {").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    assert_eq!(editor.buffer().rows.len(), 2);
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "\t  "
+    );
+    assert_eq!(editor.buffer().cursor_x, 3);
+    assert_eq!(editor.buffer().cursor_y, 1);
+
+    send_test_string(&mut editor, "y").unwrap();
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "\t  y"
+    );
+
+    // NOTE: splitting inside the indentation doesn't indent
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::ArrowRight).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "\t"
+    );
+    assert_eq!(
+        editor.buffer().rows[2].line.iter().collect::<String>(),
+        "  y"
+    );
+    assert_eq!(editor.buffer().cursor_x, 0);
+    assert_eq!(editor.buffer().cursor_y, 2);
+}
+
+#[test]
+fn test_auto_indent_block() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+
+    send_test_string(&mut editor, "  fn f() { ").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    assert_eq!(row_lines(&editor), ["  fn f() { ", "  \t"]);
+
+    editor.soft_tabs = true;
+    editor.tab_stop = 2;
+    editor.buffer_mut().syntax = Some(&SYNTAX_C);
+    send_test_string(&mut editor, "case 1:").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    assert_eq!(row_lines(&editor)[2], "  \t  ");
+
+    let (config, _) = Config::parse("auto_indent = false");
+    editor.config = config;
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    assert_eq!(row_lines(&editor)[3], "");
+}
+
+#[test]
+fn test_paste_without_indent() {
+    let code = "fn f() {\n    if x {\n        y();\n    }\n}";
+    let lines = ["fn f() {", "    if x {", "        y();", "    }", "}"];
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    editor.push_kill(code.to_string());
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), lines);
+
+    // NOTE: bracketed paste from the terminal
+    let mut editor =
+        dummy_editor(Box::new(&b"\x1b[200~"[..]), Box::new(vec![]));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::PasteStart);
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    let pasted = format!("{}\x1b[201~", code.replace('\n', "\r\n"));
+    editor.stdin = Box::new(std::io::Cursor::new(pasted));
+    editor.process_keypress(EditorKey::PasteStart).unwrap();
+    assert_eq!(row_lines(&editor), lines);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert!(row_lines(&editor).is_empty());
+}
+
+#[test]
+fn test_word_motion() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["foo(bar, baz)", "", "  qux"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    let mut positions = vec![];
+    for _ in 0..6 {
+        editor.process_keypress(EditorKey::Meta('f')).unwrap();
+        positions.push((editor.buffer().cursor_x, editor.buffer().cursor_y));
+    }
+    assert_eq!(positions, [(3, 0), (7, 0), (12, 0), (5, 2), (0, 3), (0, 3)]);
+
+    positions.clear();
+    for _ in 0..6 {
+        editor.process_keypress(EditorKey::Meta('b')).unwrap();
+        positions.push((editor.buffer().cursor_x, editor.buffer().cursor_y));
+    }
+    assert_eq!(positions, [(2, 2), (9, 0), (4, 0), (0, 0), (0, 0), (0, 0)]);
+}
+
+#[test]
+fn test_word_motion_separators() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("(define-key map)".chars().collect());
+
+    editor.process_keypress(EditorKey::Meta('f')).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 7);
+
+    // NOTE: lisp symbols may contain dashes
+    editor.buffer_mut().syntax = Some(&SYNTAX_LISP);
+    editor.buffer_mut().cursor_x = 0;
+    editor.process_keypress(EditorKey::Meta('f')).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 11);
+    editor.process_keypress(EditorKey::Meta('f')).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 15);
+    editor.process_keypress(EditorKey::Meta('b')).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 12);
+    editor.process_keypress(EditorKey::Meta('b')).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 1);
+}
+
+#[test]
+fn test_smart_home() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["    let x = 1;", "  ", "y"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.buffer_mut().cursor_x = 9;
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 4);
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 0);
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 4);
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (2, 1);
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 0);
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 0);
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (1, 2);
+    editor.process_keypress(EditorKey::Home).unwrap();
+    assert_eq!(editor.buffer().cursor_x, 0);
+}
+
+#[test]
+fn test_delete_word() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    send_test_string(&mut editor, "foo bar baz").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    editor.process_keypress(EditorKey::Meta(BACKSPACE)).unwrap();
+    assert_eq!(row_lines(&editor), ["foo "]);
+
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "    indented").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(row_lines(&editor), ["foo ", ""]);
+
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(row_lines(&editor), ["foo "]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (4, 0));
+}
+
+#[test]
+fn test_duplicate_line() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    for line in ["/* a", "b */", "c"] {
+        editor.append_row(line.chars().collect());
+    }
+    editor.buffer_mut().cursor_x = 2;
+
+    editor.stdin = Box::new(&b"\x04"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* a", "/* a", "b */", "c"]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (2, 1));
+    assert!(editor.buffer().rows[1].in_comment);
+    assert!(!editor.buffer().rows[3].in_comment);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* a", "b */", "c"]);
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (0, 3);
+    editor.process_keypress(EditorKey::Meta('d')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* a", "b */", "c"]);
+    editor.append_row(vec![]);
+    editor.process_keypress(EditorKey::Meta('d')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* a", "b */", "c", "", ""]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 4));
+
+    editor.read_only = true;
+    editor.stdin = Box::new(&b"\x04"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    editor.process_keypress(EditorKey::Meta('d')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* a", "b */", "c", "", ""]);
+}
+
+#[test]
+fn test_move_line() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    for line in ["a", "/* b", "c */", "d"] {
+        editor.append_row(line.chars().collect());
+    }
+    editor.buffer_mut().cursor_x = 1;
+
+    editor.process_keypress(EditorKey::Meta('p')).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "/* b", "c */", "d"]);
+    editor.process_keypress(EditorKey::Meta('n')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* b", "a", "c */", "d"]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 1));
+    assert!(editor.buffer().rows[1].in_comment);
+
+    editor.buffer_mut().cursor_y = 2;
+    editor.process_keypress(EditorKey::Meta('n')).unwrap();
+    editor.process_keypress(EditorKey::Meta('n')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* b", "a", "d", "c */"]);
+    assert_eq!(editor.buffer().cursor_y, 3);
+    assert!(editor.buffer().rows[2].in_comment);
+
+    editor.buffer_mut().cursor_y = 0;
+    editor.process_keypress(EditorKey::Meta('n')).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "/* b", "d", "c */"]);
+    assert!(!editor.buffer().rows[0].in_comment);
+    assert!(editor.buffer().rows[2].in_comment);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["/* b", "a", "c */", "d"]);
+    assert_eq!(editor.buffer().cursor_y, 2);
+}
+
+#[test]
+fn test_matching_bracket() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    for line in ["fn f(a: [u8; 2]) {", "    g(\")\", b); // (", "}"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    let jump = |editor: &mut Editor, from: (usize, usize)| {
+        (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = from;
+        editor.process_keypress(EditorKey::Meta('%')).unwrap();
+        (editor.buffer().cursor_x, editor.buffer().cursor_y)
+    };
+    assert_eq!(jump(&mut editor, (4, 0)), (15, 0));
+    assert_eq!(jump(&mut editor, (15, 0)), (4, 0));
+    assert_eq!(jump(&mut editor, (8, 0)), (14, 0));
+    assert_eq!(jump(&mut editor, (17, 0)), (0, 2));
+    assert_eq!(jump(&mut editor, (0, 2)), (17, 0));
+    assert_eq!(jump(&mut editor, (5, 1)), (12, 1));
+
+    // NOTE: brackets in comments have no match in code
+    assert_eq!(jump(&mut editor, (18, 1)), (18, 1));
+    assert_eq!(editor.status_msg, "No matching bracket");
+    assert_eq!(jump(&mut editor, (1, 0)), (1, 0));
+}
+
+#[test]
+fn test_matching_bracket_nested() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["f(g(", "  (x),", "  h()", ")) // end"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.buffer_mut().cursor_x = 1;
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 3));
+    editor.buffer_mut().cursor_x = 0;
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (3, 0));
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 3));
+    editor.buffer_mut().cursor_y = 1;
+    editor.buffer_mut().cursor_x = 4;
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (2, 1));
+}
+
+#[test]
+fn test_matching_bracket_highlight() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("\t(a)".chars().collect());
+
+    let draw = |editor: &Editor| {
+        let mut output = vec![];
+        editor.draw_rows(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    editor.buffer_mut().cursor_x = 1;
+    assert!(draw(&editor).contains("(a\x1b[95m)"));
+    assert!(!editor.buffer().rows[0]
+        .highlights
+        .contains(&Highlight::MatchingBracket));
+
+    editor.buffer_mut().cursor_x = 2;
+    assert!(!draw(&editor).contains("\x1b[95m"));
+
+    // NOTE: a partner below the screen isn't searched while drawing
+    editor.buffer_mut().cursor_x = 1;
+    editor.buffer_mut().rows[0].line.pop();
+    editor.update_row(0);
+    for _ in 0..100 {
+        editor.append_row(vec![]);
+    }
+    editor.append_row(vec![')']);
+    assert!(!draw(&editor).contains("\x1b[95m"));
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!(
+        (editor.buffer().cursor_x, editor.buffer().cursor_y),
+        (0, 101)
+    );
+    editor.process_keypress(EditorKey::Meta('m')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 0));
+}
+
+#[test]
+fn test_toggle_comment() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    for line in ["fn a() {", "    let x = 1;", "", "}"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (8, 1);
+    editor.process_keypress(EditorKey::Ctrl('/')).unwrap();
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "    // let x = 1;"
+    );
+    assert_eq!(editor.buffer().cursor_x, 11);
+    assert_eq!(editor.buffer().rows[1].highlights[4], Highlight::Comment);
+    editor.process_keypress(EditorKey::Ctrl('/')).unwrap();
+    assert_eq!(row_lines(&editor), ["fn a() {", "    let x = 1;", "", "}"]);
+    assert_eq!(editor.buffer().cursor_x, 8);
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (0, 0);
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.buffer_mut().cursor_y = 3;
+    editor.process_keypress(EditorKey::Ctrl('/')).unwrap();
+    assert_eq!(
+        row_lines(&editor),
+        ["// fn a() {", "    // let x = 1;", "", "}"]
+    );
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["fn a() {", "    let x = 1;", "", "}"]);
+
+    editor.buffer_mut().mark = None;
+    editor.stdin = Box::new(&b";"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(
+        editor.buffer().rows[3].line.iter().collect::<String>(),
+        "// }"
+    );
+
+    editor.buffer_mut().syntax = Some(&SYNTAX_MARKDOWN);
+    editor.process_keypress(EditorKey::Ctrl('/')).unwrap();
+    assert_eq!(editor.status_msg, "No line comments for this filetype");
+}
+
+#[test]
+fn test_shift_lines() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.soft_tabs = true;
+    editor.tab_stop = 4;
+    for line in ["a", "", "  b", "\tc"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (1, 2);
+    editor.process_keypress(EditorKey::Ctrl('i')).unwrap();
+    assert_eq!(row_lines(&editor), ["    a", "", "      b", "\tc"]);
+    assert_eq!(editor.buffer().cursor_x, 5);
+    assert!(editor.buffer().mark.is_some());
+
+    editor.process_keypress(EditorKey::BackTab).unwrap();
+    editor.process_keypress(EditorKey::BackTab).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "", "b", "\tc"]);
+    assert_eq!(editor.buffer().cursor_x, 0);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "", "  b", "\tc"]);
+
+    editor.buffer_mut().mark = None;
+    editor.buffer_mut().cursor_y = 3;
+    editor.process_keypress(EditorKey::BackTab).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "", "  b", "c"]);
+    editor.process_keypress(EditorKey::Ctrl('i')).unwrap();
+    assert_eq!(
+        editor.buffer().rows[3].line.iter().collect::<String>(),
+        "    c"
+    );
+}
+
+#[test]
+fn test_shift_selection() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["\tab", "cd"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.buffer_mut().cursor_x = 1;
+    editor.process_keypress(EditorKey::ShiftArrowRight).unwrap();
+    editor.process_keypress(EditorKey::ShiftArrowDown).unwrap();
+    assert_eq!(editor.selection(), Some(((1, 0), (2, 1))));
+
+    let mut output = vec![];
+    editor.scroll();
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\x1b[39m        \x1b[100mab\x1b[39m"));
+
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+    assert_eq!(editor.clipboard(), "ab\ncd");
+
+    editor.process_keypress(EditorKey::ShiftArrowLeft).unwrap();
+    editor.process_keypress(EditorKey::ShiftArrowUp).unwrap();
+    assert_eq!(editor.selection(), Some(((1, 0), (2, 1))));
+
+    editor.process_keypress(EditorKey::ArrowRight).unwrap();
+    assert_eq!(editor.buffer().mark, None);
+
+    // NOTE: a mark set with C-SPC stays through plain motion
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::ShiftArrowRight).unwrap();
+    editor.process_keypress(EditorKey::ArrowRight).unwrap();
+    assert_eq!(editor.selection(), Some(((2, 0), (0, 1))));
+}
+
+#[test]
+fn test_mouse() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["\tab", "hello", "x"] {
+        editor.append_row(line.chars().collect());
+    }
+    let left = editor.line_number_space();
+
+    let stdin = format!("\x1b[<0;{};1M\x1b[<32;{};2M", left + 10, left + 4);
+    editor.stdin = Box::new(std::io::Cursor::new(stdin));
+    let key = editor.read_key().unwrap();
+    assert_eq!(
+        key,
+        EditorKey::Mouse {
+            x: left + 9,
+            y: 0,
+            pressed: true,
+            drag: false
+        }
+    );
+    editor.process_keypress(key).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (2, 0));
+
+    let key = editor.read_key().unwrap();
+    editor.process_keypress(key).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (3, 1));
+    assert_eq!(editor.selection(), Some(((2, 0), (3, 1))));
+
+    let release = EditorKey::Mouse {
+        x: left + 1,
+        y: 5,
+        pressed: false,
+        drag: false,
+    };
+    editor.process_keypress(release).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 3));
+    assert!(editor.buffer().mark.is_some());
+
+    editor.buffer_mut().row_offset = 1;
+    for pressed in [true, false] {
+        let key = EditorKey::Mouse {
+            x: left + 50,
+            y: 1,
+            pressed,
+            drag: false,
+        };
+        editor.process_keypress(key).unwrap();
+    }
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 2));
+    assert_eq!(editor.buffer().mark, None);
+
+    editor.stdin = Box::new(&b"\x1b[<2;1;1M\x1b[<64;1;1M\x1b[<65;1;1M"[..]);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Other(ESC));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::WheelUp);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::WheelDown);
+
+    // NOTE: modifiers are ignored
+    editor.stdin = Box::new(&b"\x1b[<16;2;3M\x1b[<81;1;1M"[..]);
+    assert_eq!(
+        editor.read_key().unwrap(),
+        EditorKey::Mouse {
+            x: 1,
+            y: 2,
+            pressed: true,
+            drag: false
+        }
+    );
+    assert_eq!(editor.read_key().unwrap(), EditorKey::WheelDown);
+}
+
+#[test]
+fn test_mouse_wheel() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.screen_rows = 5;
+    for n in 0..10 {
+        editor.append_row(format!("line {}", n).chars().collect());
+    }
+    editor.buffer_mut().cursor_x = 6;
+
+    editor.process_keypress(EditorKey::WheelDown).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 3);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (6, 3));
+
+    editor.process_keypress(EditorKey::WheelDown).unwrap();
+    editor.process_keypress(EditorKey::WheelDown).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 5);
+    assert_eq!(editor.buffer().cursor_y, 5);
+
+    editor.buffer_mut().cursor_y = 9;
+    editor.process_keypress(EditorKey::WheelUp).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 2);
+    assert_eq!(editor.buffer().cursor_y, 6);
+}
+
+#[test]
+fn test_recenter() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.screen_rows = 5;
+    for n in 0..20 {
+        editor.append_row(format!("line {}", n).chars().collect());
+    }
+    editor.buffer_mut().cursor_y = 10;
+
+    let mut offsets = vec![];
+    for _ in 0..4 {
+        editor.process_keypress(EditorKey::Ctrl('l')).unwrap();
+        editor.scroll();
+        offsets.push(editor.buffer().row_offset);
+    }
+    assert_eq!(offsets, [8, 10, 6, 8]);
+
+    // NOTE: any other key starts the cycle again
+    editor.process_keypress(EditorKey::ArrowUp).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('l')).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 7);
+
+    editor.buffer_mut().cursor_y = 1;
+    editor.process_keypress(EditorKey::Ctrl('l')).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 1);
+    editor.process_keypress(EditorKey::Ctrl('l')).unwrap();
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 0);
+}
+
+#[test]
+fn test_wrap_lines() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["0123456789abc", "x"] {
+        editor.append_row(line.chars().collect());
+    }
+    let left = editor.line_number_space();
+    editor.screen_cols = left + 8;
+    editor.process_keypress(EditorKey::Meta('w')).unwrap();
+    assert!(editor.wrap_lines);
+
+    editor.buffer_mut().cursor_x = 10;
+    editor.scroll();
+    assert_eq!(editor.screen_lines()[..3], [(0, 0), (0, 8), (1, 0)]);
+    assert_eq!(editor.cursor_screen_position(), (1, 2));
+
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("01234567\x1b[39m"));
+    let continuation = format!("\n{}│ \x1b[39m89abc", " ".repeat(left - 2));
+    assert!(output.contains(&continuation));
+
+    editor.screen_rows = 2;
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (0, 1);
+    editor.scroll();
+    assert_eq!(editor.buffer().row_offset, 1);
+    assert_eq!(editor.cursor_screen_position(), (0, 0));
+
+    editor.buffer_mut().row_offset = 0;
+    editor.click((editor.line_number_space() + 3, 1), true, false);
+    assert_eq!(
+        (editor.buffer().cursor_x, editor.buffer().cursor_y),
+        (editor.editor_cols + 3, 0)
+    );
+
+    let mut moves = vec![];
+    for key in [
+        EditorKey::ArrowUp,
+        EditorKey::ArrowDown,
+        EditorKey::ArrowDown,
+        EditorKey::ArrowUp,
+    ] {
+        editor.move_cursor(key);
+        moves.push((editor.buffer().cursor_x, editor.buffer().cursor_y));
+    }
+    let cols = editor.editor_cols;
+    assert_eq!(moves, [(3, 0), (cols + 3, 0), (1, 1), (cols + 1, 0)]);
+}
+
+#[test]
+fn test_greeting() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    let draw = |editor: &mut Editor| {
+        let mut output = vec![];
+        editor.scroll();
+        editor.draw_rows(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let output = draw(&mut editor);
+    let lines = output.split("\r\n").collect::<Vec<_>>();
+    assert!(lines[0].starts_with('~'));
+    assert!(lines[editor.screen_rows / 3].contains("~  "));
+    assert!(lines[editor.screen_rows / 3].contains("red editor -- version"));
+
+    let (config, _) = Config::parse("show_tildes = no\ngreeting = Hello 世界");
+    let mut editor = Editor::headless(config);
+    editor.screen_rows = 9;
+    editor.screen_cols = 20;
+    let output = draw(&mut editor);
+    assert!(!output.contains('~'));
+    let lines = output.split("\r\n").collect::<Vec<_>>();
+    let padding = (editor.editor_cols - "Hello 世界".chars().count()) / 2;
+    assert!(lines[3].starts_with(&format!("{}Hello 世界", " ".repeat(padding))));
+
+    editor.greeting.clear();
+    assert!(!draw(&mut editor).contains("Hello"));
+}
+
+#[test]
+fn test_hide_line_numbers() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.process_keypress(EditorKey::Meta('l')).unwrap();
+    assert!(!editor.line_numbers);
+    assert_eq!(editor.line_number_space(), 0);
+
+    editor.scroll();
+    assert_eq!(editor.editor_cols, editor.screen_cols);
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let welcome = output.split("\r\n").nth(editor.screen_rows / 3).unwrap();
+    let text = welcome.trim_start_matches(['~', ' ']);
+    let text = text.trim_end_matches("\x1b[K");
+    let padding = welcome.len() - welcome.trim_start_matches(['~', ' ']).len();
+    assert_eq!(padding, (editor.screen_cols - text.len()) / 2);
+
+    editor.append_row("abc".chars().collect());
+    editor.buffer_mut().cursor_x = 2;
+    let mut output = vec![];
+    editor.scroll();
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("\x1b[39mabc"));
+    assert_eq!(editor.cursor_screen_position(), (0, 2));
+}
+
+#[test]
+fn test_cursor_line() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("abc".chars().collect());
+    editor.append_row("de".chars().collect());
+    editor.process_keypress(EditorKey::Meta('c')).unwrap();
+    assert!(editor.cursor_line);
+
+    editor.buffer_mut().mark = Some((0, 0));
+    editor.buffer_mut().cursor_x = 2;
+    editor.scroll();
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.split("\r\n").collect::<Vec<_>>();
+    let fill = " ".repeat(editor.editor_cols - 3);
+    assert!(lines[0].ends_with(&format!(
+        "\x1b[100m\x1b[39mab\x1b[40mc\x1b[40m{}\x1b[39m\x1b[49m\x1b[K",
+        fill
+    )));
+    assert!(!lines[1].contains("\x1b[40m"));
+
+    let depth = crate::ColorDepth::TrueColor;
+    let theme = crate::THEMES[0].with_depth(depth);
+    assert_eq!(theme.cursor_line.as_ref(), b"\x1b[40m");
+}
+
+#[test]
+fn test_wide_chars() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("a中b😀".chars().collect());
+    let draw = |editor: &Editor| {
+        let mut output = vec![];
+        editor.draw_rows(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(editor.buffer().rows[0].render.len(), 6);
+    editor.buffer_mut().cursor_x = 2;
+    editor.scroll();
+    assert_eq!(editor.render_x, 3);
+    assert_eq!(editor.cursor_screen_position(), (0, 3));
+    assert!(draw(&editor).contains("\x1b[39ma中b😀\x1b[39m"));
+
+    editor.buffer_mut().col_offset = 2;
+    assert!(draw(&editor).contains("│  \x1b[39mb😀\x1b[39m"));
+
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, false).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "a中b😀\n");
+
+    editor.wrap_lines = true;
+    editor.screen_cols = editor.line_number_space() + 5;
+    editor.buffer_mut().cursor_x = 3;
+    editor.scroll();
+    assert_eq!(editor.wrap_starts(&editor.buffer().rows[0]), [0, 4]);
+    assert_eq!(editor.cursor_screen_position(), (1, 0));
+    assert!(draw(&editor).contains("\x1b[39ma中b \x1b[39m"));
+}
+
+#[test]
+fn test_combining_marks() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("e\u{301}x漢\u{308}".chars().collect());
+
+    assert_eq!(editor.buffer().rows[0].render, ['e', 'x', '漢', '漢']);
+    editor.move_cursor(EditorKey::ArrowRight);
+    assert_eq!(editor.buffer().cursor_x, 2);
+    editor.move_cursor(EditorKey::ArrowLeft);
+    assert_eq!(editor.buffer().cursor_x, 0);
+    editor.buffer_mut().cursor_x = 5;
+    editor.scroll();
+    assert_eq!(editor.render_x, 4);
+    editor.move_cursor(EditorKey::ArrowLeft);
+    assert_eq!(editor.buffer().cursor_x, 3);
+    editor.buffer_mut().cursor_x = 1;
+    editor.scroll();
+    assert_eq!((editor.buffer().cursor_x, editor.render_x), (2, 1));
+    editor.insert_char('y');
+    assert_eq!(editor.buffer().rows[0].line[..3], ['e', '\u{301}', 'y']);
+
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\x1b[39me\u{301}yx漢\u{308}\x1b[39m"));
+
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, false).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "e\u{301}yx漢\u{308}\n");
+}
+
+#[test]
+fn test_show_tabs() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("\tx\ty".chars().collect());
+    let draw = |editor: &Editor| {
+        let mut output = vec![];
+        editor.draw_rows(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert!(!draw(&editor).contains("→"));
+    editor.process_keypress(EditorKey::Meta('s')).unwrap();
+    assert_eq!(editor.tab_columns(&editor.buffer().rows[0]), [0, 9]);
+    let output = draw(&editor);
+    assert!(
+        output.contains("│ \x1b[2m→\x1b[22m\x1b[39m       x\x1b[2m→\x1b[22m")
+    );
+    assert_eq!(
+        editor.buffer().rows[0]
+            .render
+            .iter()
+            .filter(|&&c| c == ' ')
+            .count(),
+        15
+    );
+}
+
+#[test]
+fn test_kill() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["let x = foo(bar);", "", "end"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.buffer_mut().cursor_x = 15;
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = foo();", "", "end"]);
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = );", "", "end"]);
+    assert_eq!(editor.buffer().cursor_x, 8);
+
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = ", "", "end"]);
+    assert_eq!(editor.clipboard(), ");");
+
+    // NOTE: at the end of the line the newline is killed and consecutive
+    // kills are collected
+    editor.process_keypress(EditorKey::Ctrl('l')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = end"]);
+    assert_eq!(editor.clipboard(), "\n\n");
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = "]);
+    assert_eq!(editor.clipboard(), "\n\nend");
+
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = ", "", "end"]);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = end"]);
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["let x = ", "end"]);
 }
 
 #[test]
@@ -212,8 +1131,11 @@ fn test_deletion() {
 
     editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
 
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "hello");
-    assert_eq!(editor.rows[1].line.iter().collect::<String>(), "");
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "hello"
+    );
+    assert_eq!(editor.buffer().rows[1].line.iter().collect::<String>(), "");
 
     editor
         .process_keypress(EditorKey::Other(BACKSPACE))
@@ -225,21 +1147,24 @@ fn test_deletion() {
         .process_keypress(EditorKey::Other(BACKSPACE))
         .unwrap();
 
-    assert_eq!(editor.rows.len(), 1);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "hel");
+    assert_eq!(editor.buffer().rows.len(), 1);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "hel"
+    );
 
     editor.process_keypress(EditorKey::ArrowLeft).unwrap();
     editor.process_keypress(EditorKey::ArrowLeft).unwrap();
     editor.process_keypress(EditorKey::ArrowLeft).unwrap();
 
-    assert_eq!(editor.cursor_x, 0);
-    assert_eq!(editor.cursor_y, 0);
+    assert_eq!(editor.buffer().cursor_x, 0);
+    assert_eq!(editor.buffer().cursor_y, 0);
 
     editor.process_keypress(EditorKey::Delete).unwrap();
     editor.process_keypress(EditorKey::Delete).unwrap();
 
-    assert_eq!(editor.rows.len(), 1);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "l");
+    assert_eq!(editor.buffer().rows.len(), 1);
+    assert_eq!(editor.buffer().rows[0].line.iter().collect::<String>(), "l");
 
     editor.process_keypress(EditorKey::Delete).unwrap();
     editor.process_keypress(EditorKey::Delete).unwrap();
@@ -248,8 +1173,8 @@ fn test_deletion() {
     editor.process_keypress(EditorKey::Delete).unwrap();
     editor.process_keypress(EditorKey::Delete).unwrap();
 
-    assert_eq!(editor.rows.len(), 1);
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "");
+    assert_eq!(editor.buffer().rows.len(), 1);
+    assert_eq!(editor.buffer().rows[0].line.iter().collect::<String>(), "");
 }
 
 #[test]
@@ -262,40 +1187,430 @@ fn test_copy_paste() {
 
     editor.process_keypress(EditorKey::Home).unwrap();
 
-    assert_eq!(editor.cursor_x, 0);
-    assert_eq!(editor.cursor_y, 0);
+    assert_eq!(editor.buffer().cursor_x, 0);
+    assert_eq!(editor.buffer().cursor_y, 0);
 
     editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
-    assert_eq!(editor.mark, Some((0, 0)));
+    assert_eq!(editor.buffer().mark, Some((0, 0)));
 
     editor.process_keypress(EditorKey::ArrowRight).unwrap();
     editor.process_keypress(EditorKey::ArrowRight).unwrap();
     editor.process_keypress(EditorKey::ArrowRight).unwrap();
     editor.process_keypress(EditorKey::ArrowRight).unwrap();
     editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
-    assert_eq!(editor.clipboard, "this");
+    assert_eq!(editor.clipboard(), "this");
 
     editor.process_keypress(EditorKey::End).unwrap();
-    assert_eq!(editor.cursor_x, 14);
-    assert_eq!(editor.cursor_y, 0);
+    assert_eq!(editor.buffer().cursor_x, 14);
+    assert_eq!(editor.buffer().cursor_y, 0);
 
     editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
     editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
     editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
 
-    assert_eq!(editor.rows.len(), 1);
+    assert_eq!(editor.buffer().rows.len(), 1);
     assert_eq!(
-        editor.rows[0].line.iter().collect::<String>(),
+        editor.buffer().rows[0].line.iter().collect::<String>(),
         "this is a testthisthisthis"
     );
 }
 
 #[test]
-fn test_update_syntax() {
-    let stdin = b"";
+fn test_copy_line() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+    assert!(editor.kill_ring.is_empty());
+
+    for line in ["first", "second"] {
+        editor.append_row(line.chars().collect());
+    }
+    editor.buffer_mut().cursor_x = 3;
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+    assert_eq!(editor.clipboard(), "first\n");
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (0, 1);
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["first", "first", "second"]);
+}
+
+#[test]
+fn test_cut() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    send_test_string(&mut editor, "this is a test").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "line").unwrap();
+
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (5, 0);
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(editor.clipboard(), "is a test\nline");
+    assert_eq!(row_lines(&editor), ["this "]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (5, 0));
+    assert_eq!(editor.buffer().mark, None);
+    assert!(editor.buffer().dirty);
+
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["this is a test", "line"]);
+
+    // NOTE: without a selection the previous word is deleted
+    editor.process_keypress(EditorKey::Ctrl('w')).unwrap();
+    assert_eq!(row_lines(&editor), ["this is a test", ""]);
+    assert_eq!(editor.clipboard(), "is a test\nline");
+}
+
+#[test]
+fn test_kill_ring() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+
+    // NOTE: pasting and cycling an empty ring does nothing
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    assert_eq!(editor.status_msg, "Previous command was not a paste");
+
+    send_test_string(&mut editor, "one two").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "three").unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    editor.process_keypress(EditorKey::ArrowUp).unwrap();
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::Meta('f')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+    assert_eq!(editor.kill_ring, ["three", "one"]);
+
+    editor.process_keypress(EditorKey::End).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["one twoone", ""]);
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    assert_eq!(row_lines(&editor), ["one twothree", ""]);
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    assert_eq!(row_lines(&editor), ["one twoone", ""]);
+    assert_eq!(editor.buffer().cursor_x, 10);
+
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["one twoone", ""]);
+
+    // NOTE: only the newest kills are kept
+    for n in 0..RED_KILL_RING_SIZE {
+        editor.push_kill(n.to_string());
+    }
+    assert_eq!(editor.kill_ring.len(), RED_KILL_RING_SIZE);
+    assert_eq!(editor.kill_ring[0], "0");
+    assert_eq!(editor.clipboard(), (RED_KILL_RING_SIZE - 1).to_string());
+}
+
+#[test]
+fn test_base64() {
+    for (plain, encoded) in [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("äöü\n", "w6TDtsO8Cg=="),
+    ] {
+        assert_eq!(base64_encode(plain.as_bytes()), encoded);
+        assert_eq!(base64_decode(encoded).unwrap(), plain.as_bytes());
+    }
+
+    assert_eq!(base64_decode("Z"), None);
+    assert_eq!(base64_decode("Z!=="), None);
+}
+
+#[test]
+fn test_osc52() {
+    assert_eq!(
+        osc52_copy("foo", "xterm-256color").unwrap(),
+        b"\x1b]52;c;Zm9v\x07"
+    );
+    assert_eq!(
+        osc52_copy("foo", "tmux").unwrap(),
+        b"\x1bPtmux;\x1b\x1b]52;c;Zm9v\x07\x1b\\"
+    );
+
+    // NOTE: 8 + 268 bytes are split into chunks of 76 bytes
+    let screen = osc52_copy(&"x".repeat(200), "screen").unwrap();
+    let chunks = screen.windows(2).filter(|w| w == b"\x1bP").count();
+    assert_eq!(chunks, 4);
+    assert!(osc52_copy(&"x".repeat(80_000), "xterm").is_none());
+
+    assert_eq!(
+        osc52_parse_reply(b"\x1b]52;c;Zm9v\x07"),
+        Some("foo".to_string())
+    );
+    assert_eq!(
+        osc52_parse_reply(b"\x1b]52;c;Zm9v\x1b\\"),
+        Some("foo".to_string())
+    );
+    assert_eq!(osc52_parse_reply(b"garbage"), None);
+
+    assert_eq!(osc52_reply_range(b"ab\x1b]52;c;Zm9v\x07c"), Some(2..14));
+    assert_eq!(osc52_reply_range(b"\x1b]52;c;Zm9v\x1b\\"), Some(0..13));
+    assert_eq!(osc52_reply_range(b"\x1b]52;c;Zm9"), None);
+}
+
+#[test]
+fn test_system_clipboard() {
+    let mut output = vec![];
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(&mut output));
+    editor.use_system_clipboard = true;
+    editor.config.clipboard_read = true;
+    editor.terminal = "xterm".to_string();
+
+    send_test_string(&mut editor, "foo").unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::End).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+
+    // NOTE: the terminal answers with the system clipboard
+    editor.stdin = Box::new(&b"\x1b]52;c;YmFy\x07"[..]);
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["foobar"]);
+
+    // NOTE: no answer, fall back to the internal clipboard
+    editor.stdin = Box::new(&b""[..]);
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["foobarbar"]);
+
+    // NOTE: keys typed while waiting for the answer aren't lost
+    editor.stdin = Box::new(&b"x\x1b]52;c;YmF6\x07y"[..]);
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["foobarbarbaz"]);
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Other('x'));
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Other('y'));
+
+    editor.stdin = Box::new(&b"z"[..]);
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(editor.read_key().unwrap(), EditorKey::Other('z'));
+
+    // NOTE: without `clipboard_read` there is no query
+    editor.config.clipboard_read = false;
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+
+    drop(editor);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\x1b]52;c;Zm9v\x07"));
+    assert_eq!(output.matches("\x1b]52;c;?\x07").count(), 4);
+}
+
+#[test]
+fn test_repeat_last_change() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+
+    editor.process_keypress(EditorKey::Meta('.')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+
+    send_test_string(&mut editor, "ab").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "c").unwrap();
+    assert_eq!(
+        editor.last_change,
+        Some(Change::Insert(vec!['a', 'b', '\n', 'c']))
+    );
+
+    editor.process_keypress(EditorKey::ArrowUp).unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Meta('.')).unwrap();
+    assert_eq!(editor.buffer().rows.len(), 3);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "ab"
+    );
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "cab"
+    );
+    assert_eq!(editor.buffer().rows[2].line.iter().collect::<String>(), "c");
+
+    editor.process_keypress(EditorKey::End).unwrap();
+    editor
+        .process_keypress(EditorKey::Other(BACKSPACE))
+        .unwrap();
+    editor
+        .process_keypress(EditorKey::Other(BACKSPACE))
+        .unwrap();
+    assert_eq!(editor.last_change, Some(Change::DeleteBackward(2)));
+    assert_eq!(editor.buffer().rows[1].line.iter().collect::<String>(), "c");
+    editor.process_keypress(EditorKey::Meta('.')).unwrap();
+    assert_eq!(editor.buffer().rows.len(), 2);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "ab"
+    );
+    assert_eq!(editor.buffer().rows[1].line.iter().collect::<String>(), "c");
+
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Delete).unwrap();
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Meta('.')).unwrap();
+    assert_eq!(editor.last_change, Some(Change::DeleteForward(1)));
+    assert_eq!(editor.buffer().rows[0].line.iter().collect::<String>(), "b");
+    assert_eq!(editor.buffer().rows[1].line.iter().collect::<String>(), "");
+
+    editor.push_kill(String::from("xy"));
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    editor.process_keypress(EditorKey::Meta('.')).unwrap();
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "xyxy"
+    );
+}
+
+fn row_lines(editor: &Editor) -> Vec<String> {
+    editor
+        .buffer()
+        .rows
+        .iter()
+        .map(|row| row.line.iter().collect())
+        .collect()
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+
+    send_test_string(&mut editor, "abc").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "de").unwrap();
+    assert_eq!(row_lines(&editor), ["abc", "de"]);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["abc", ""]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 1));
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["abc"]);
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(editor.status_msg, "Nothing to undo");
+
+    editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+    assert_eq!(row_lines(&editor), ["abc", ""]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 1));
+
+    // NOTE: a new edit drops the redo history
+    send_test_string(&mut editor, "x").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+    assert_eq!(editor.status_msg, "Nothing to redo");
+    assert_eq!(row_lines(&editor), ["abc", "x"]);
+
+    // NOTE: deleting a selection is a single step
+    editor.process_keypress(EditorKey::ArrowUp).unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+    editor
+        .process_keypress(EditorKey::Other(BACKSPACE))
+        .unwrap();
+    assert_eq!(row_lines(&editor), ["x"]);
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["abc", "x"]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 1));
+}
+
+#[test]
+fn test_undo_to_saved_state() {
+    let file = NamedTempFile::new().unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+
+    send_test_string(&mut editor, "ab").unwrap();
+    editor.save().unwrap();
+    send_test_string(&mut editor, "c").unwrap();
+    assert!(editor.buffer().dirty);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["ab"]);
+    assert!(!editor.buffer().dirty);
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert!(editor.buffer().dirty);
+    editor.process_keypress(EditorKey::Meta('z')).unwrap();
+    assert!(!editor.buffer().dirty);
+
+    // NOTE: editing after undoing past the save point loses it for good
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    send_test_string(&mut editor, "x").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+    assert!(editor.buffer().dirty);
+}
+
+#[test]
+fn test_undo_limit() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    let (config, warnings) = Config::parse("undo_limit = 2");
+    assert!(warnings.is_empty());
+    editor.config = config;
+
+    for _ in 0..4 {
+        send_test_string(&mut editor, "ab").unwrap();
+        editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    }
+    for _ in 0..4 {
+        editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    }
+
+    assert_eq!(row_lines(&editor), ["ab", "ab", "ab", ""]);
+}
+
+fn edit_key() -> impl Strategy<Value = EditorKey> {
+    prop_oneof![
+        prop::char::range('a', 'c').prop_map(EditorKey::Other),
+        Just(EditorKey::Ctrl('m')),
+        Just(EditorKey::Other(BACKSPACE)),
+        Just(EditorKey::Delete),
+        Just(EditorKey::ArrowLeft),
+        Just(EditorKey::ArrowRight),
+        Just(EditorKey::ArrowUp),
+        Just(EditorKey::ArrowDown),
+        Just(EditorKey::Ctrl(' ')),
+        Just(EditorKey::Ctrl('c')),
+        Just(EditorKey::Ctrl('v')),
+        Just(EditorKey::Meta('.')),
+        Just(EditorKey::Meta('t')),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_undo_everything(keys in prop::collection::vec(edit_key(), 0..40)) {
+        let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+        for line in ["ab", "", "c ", ""] {
+            editor.append_row(line.chars().collect());
+        }
+        let original = row_lines(&editor);
+
+        for &key in &keys {
+            editor.process_keypress(key).unwrap();
+        }
+        let edited = row_lines(&editor);
+
+        for _ in 0..keys.len() {
+            editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+        }
+        prop_assert_eq!(row_lines(&editor), original);
+
+        for _ in 0..keys.len() {
+            editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+        }
+        prop_assert_eq!(row_lines(&editor), edited);
+    }
+}
+
+#[test]
+fn test_update_syntax() {
+    let stdin = b"";
     let stdout = vec![];
     let mut editor = dummy_editor(Box::new(&stdin[..]), Box::new(stdout));
-    editor.syntax = Some(&SYNTAX_RUST);
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
     send_test_string(&mut editor, "/*").unwrap();
     editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
     editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
@@ -304,13 +1619,174 @@ fn test_update_syntax() {
     editor.process_keypress(EditorKey::ArrowUp).unwrap();
     send_test_string(&mut editor, "x").unwrap();
 
-    assert_eq!(editor.cursor_y, 2);
-    assert!(editor.rows[editor.cursor_y].in_comment);
+    assert_eq!(editor.buffer().cursor_y, 2);
+    assert!(editor.buffer().rows[editor.buffer().cursor_y].in_comment);
+}
+
+#[test]
+fn test_line_endings() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "one\r\ntwo\r\n").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    assert_eq!(row_lines(&editor), ["one", "two"]);
+    assert_eq!(editor.buffer().line_ending, LineEnding::CrLf);
+
+    editor.save().unwrap();
+    let content = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "one\r\ntwo\r\n");
+
+    std::fs::write(file.path(), "a\r\nb\r\nc\nd").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    assert_eq!(row_lines(&editor), ["a", "b", "c", "d"]);
+    assert_eq!(editor.buffer().line_ending, LineEnding::CrLf);
+
+    editor.save().unwrap();
+    assert!(editor.status_msg.ends_with("(mixed endings -> CRLF)"));
+    let content = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "a\r\nb\r\nc\r\nd");
+}
+
+#[test]
+fn test_strip_trailing_whitespace() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "a  \nb\n\t\nc \t\n").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+
+    editor.save().unwrap();
+    let content = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "a  \nb\n\t\nc \t\n");
+
+    let (config, _) = Config::parse("strip_trailing_whitespace = yes");
+    editor.config = config;
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (3, 3);
+    editor.save().unwrap();
+    assert!(editor.status_msg.ends_with(", stripped 3 lines"));
+    let content = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "a\nb\n\nc\n");
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 3));
+    assert!(!editor.buffer().dirty);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["a  ", "b", "\t", "c \t"]);
+}
+
+#[test]
+fn test_final_newline() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "a\nb").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    assert!(editor.buffer().missing_final_newline);
+
+    editor.save().unwrap();
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "a\nb");
+    assert_eq!(editor.status_msg, "3 bytes written to disk");
+
+    let (config, _) = Config::parse("ensure_final_newline = yes");
+    editor.config = config;
+    editor.save().unwrap();
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "a\nb\n");
+
+    editor.buffer_mut().rows.clear();
+    editor.open(file.path()).unwrap();
+    assert!(!editor.buffer().missing_final_newline);
+    editor.config.ensure_final_newline = false;
+    editor.save().unwrap();
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "a\nb\n");
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(b"caf\xe9\n").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    assert_eq!(row_lines(&editor), ["caf\u{fffd}"]);
+    assert!(editor.buffer().invalid_utf8);
+    let mut status_bar = vec![];
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar.contains("| UTF-8 (lossy) LF |"));
+
+    editor.stdin = Box::new(&b"n"[..]);
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "Save aborted");
+    assert_eq!(std::fs::read(file.path()).unwrap(), b"caf\xe9\n");
+
+    editor.stdin = Box::new(&b"y"[..]);
+    editor.save().unwrap();
+    assert!(!editor.buffer().invalid_utf8);
+    let content = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "caf\u{fffd}\n");
+}
+
+#[test]
+fn test_reload() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "one\ntwo\nthree\n").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    editor.buffer_mut().cursor_y = 2;
+    editor.buffer_mut().cursor_x = 4;
+
+    std::fs::write(file.path(), "one\ntwo\n").unwrap();
+    editor.stdin = Box::new(&b"\x12"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(row_lines(&editor), ["one", "two"]);
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (3, 1));
+    assert_eq!(editor.status_msg, "Reloaded 2 lines");
+
+    // NOTE: unsaved changes are only dropped after confirmation
+    send_test_string(&mut editor, "!").unwrap();
+    editor.stdin = Box::new(&b"\x12n"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(row_lines(&editor), ["one", "two!"]);
+    assert_eq!(editor.status_msg, "Reload aborted");
+
+    editor.stdin = Box::new(&b"y"[..]);
+    editor.process_keypress(EditorKey::Meta('e')).unwrap();
+    assert_eq!(row_lines(&editor), ["one", "two"]);
+    assert!(!editor.buffer().dirty);
+}
+
+#[test]
+fn test_read_only() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("text".chars().collect());
+    editor.read_only = true;
+
+    for key in [
+        EditorKey::Other('x'),
+        EditorKey::Ctrl('m'),
+        EditorKey::Ctrl('h'),
+        EditorKey::Ctrl('v'),
+        EditorKey::Ctrl('s'),
+    ] {
+        editor.process_keypress(key).unwrap();
+        assert_eq!(editor.status_msg, "Buffer is read-only");
+    }
+    assert_eq!(row_lines(&editor), ["text"]);
+    assert!(!editor.buffer().dirty);
+
+    editor.process_keypress(EditorKey::ArrowRight).unwrap();
+    editor.process_keypress(EditorKey::Ctrl(' ')).unwrap();
+    editor.process_keypress(EditorKey::End).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('c')).unwrap();
+    assert_eq!(editor.clipboard(), "ext");
+
+    let mut status_bar = vec![];
+    editor.screen_cols = 80;
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar.contains("[No Name] [RO] - 1 lines"));
 }
 
 #[test]
 fn test_draw_status_bar() {
-    let stdin = vec![];
+    let stdin = [];
     let stdout = vec![];
     let mut status_bar = vec![];
     let mut editor = dummy_editor(Box::new(&stdin[..]), Box::new(stdout));
@@ -326,28 +1802,32 @@ fn test_draw_status_bar() {
         (
             None,
             None,
-            "[No Name] - 4 lines (modified)                   no ft | 3/4",
+            "[No - 4 lines (modified) no ft | UTF-8 LF | tabs | 3/4:1 75%",
         ),
         (
             Some(&SYNTAX_HASKELL),
             Some(PathBuf::from("main.hs")),
-            "main.hs - 4 lines                              haskell | 2/4",
+            "main.hs - 4 lines    haskell | UTF-8 CRLF | tabs | 2/4:1 50%",
         ),
         (
             Some(&SYNTAX_C),
             Some(PathBuf::from("test.c")),
-            "test.c - 4 lines (modified)                          c | 1/4",
+            "test.c - 4 lines (modified)  c | UTF-8 LF | tabs | 1/4:1 25%",
         ),
     ];
 
-    editor.dirty = false;
+    editor.buffer_mut().dirty = false;
 
     for (syntax, file, expected) in tests {
-        editor.syntax = syntax;
-        editor.file = file;
+        editor.buffer_mut().line_ending = match syntax {
+            Some(&SYNTAX_HASKELL) => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        editor.buffer_mut().syntax = syntax;
+        editor.buffer_mut().file = file;
 
         editor.process_keypress(EditorKey::ArrowUp).unwrap();
-        editor.dirty = !editor.dirty;
+        editor.buffer_mut().dirty = !editor.buffer().dirty;
 
         editor.draw_status_bar(&mut status_bar).unwrap();
         assert!(status_bar.starts_with(ESC_SEQ_INVERT_COLORS));
@@ -366,6 +1846,239 @@ fn test_draw_status_bar() {
 
         status_bar.clear();
     }
+
+    editor.render_x = 11;
+    editor.screen_cols = 40;
+    editor.editor_cols = 40;
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar.contains("(modified)   1/4:12 25%"));
+
+    for (cols, expected) in [
+        (20, "test.c -  1/4:12 25%"),
+        (8, "1/4:12 2"),
+        (37, "test.c - 4 lines (modified 1/4:12 25%"),
+        (38, "test.c - 4 lines (modified) 1/4:12 25%"),
+    ] {
+        let mut status_bar = vec![];
+        editor.screen_cols = cols;
+        editor.draw_status_bar(&mut status_bar).unwrap();
+        let status_bar = String::from_utf8(status_bar).unwrap();
+        assert!(status_bar.contains(&format!("\x1b[7m{}\x1b[m", expected)));
+    }
+}
+
+#[test]
+fn test_tiny_terminal() {
+    for (rows, cols) in [(2, 1), (1, 80), (0, 0), (3, 4)] {
+        let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+        editor.set_window_size(rows, cols);
+        assert!(editor.is_too_small());
+
+        send_test_string(&mut editor, "hello").unwrap();
+        editor.refresh_screen().unwrap();
+    }
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.set_window_size(3, 10);
+    assert!(!editor.is_too_small());
+    assert_eq!(editor.screen_rows, 1);
+
+    send_test_string(&mut editor, "hello world").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(editor.buffer().row_offset, 1);
+}
+
+#[test]
+fn test_window_resize() {
+    let mut output = vec![];
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(&mut output));
+    editor.window_size = || Ok((12, 40));
+    for n in 0..20 {
+        editor.append_row(format!("line {}", n).chars().collect());
+    }
+    editor.buffer_mut().cursor_y = 15;
+
+    editor.win_changed.store(true, Ordering::Relaxed);
+    editor.refresh_screen().unwrap();
+    assert!(!editor.win_changed.load(Ordering::Relaxed));
+    assert_eq!((editor.screen_rows, editor.screen_cols), (10, 40));
+    assert_eq!(editor.editor_cols, 40 - editor.line_number_space());
+    assert_eq!(editor.buffer().row_offset, 6);
+
+    // NOTE: only a resize clears the whole screen
+    editor.refresh_screen().unwrap();
+    drop(editor);
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("\x1b[2J").count(), 1);
+}
+
+#[test]
+fn test_draw_changed_rows() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.buffer_mut().syntax = Some(&SYNTAX_RUST);
+    for n in 0..100 {
+        editor.append_row(
+            format!("let x{} = \"中\"; // {}", n, n).chars().collect(),
+        );
+    }
+    editor.scroll();
+
+    let mut first = vec![];
+    editor.draw_changed_rows(&mut first).unwrap();
+    let mut second = vec![];
+    editor.draw_changed_rows(&mut second).unwrap();
+    assert!(first.len() > 48 * 40);
+    assert_eq!(second, b"\r\n".repeat(48));
+
+    // NOTE: moving the cursor changes the line numbers of two rows
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+    editor.buffer_mut().rows[10].line.push('x');
+    editor.update_row(10);
+    let mut third = vec![];
+    editor.draw_changed_rows(&mut third).unwrap();
+    let third = String::from_utf8(third).unwrap();
+    let changed: Vec<_> = third.split("\r\n").map(|l| !l.is_empty()).collect();
+    assert_eq!(changed.iter().filter(|&&c| c).count(), 3);
+    assert!(changed[0] && changed[1] && changed[10]);
+
+    editor.recenter(0);
+    let mut fourth = vec![];
+    editor.draw_changed_rows(&mut fourth).unwrap();
+    assert_eq!(fourth.len(), first.len() + "x".len());
+}
+
+#[test]
+fn test_trailing_blank_lines() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    let mut status_bar = vec![];
+
+    send_test_string(&mut editor, "text").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, " \t").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+
+    assert_eq!(editor.buffer().rows.len(), 4);
+    assert_eq!(editor.trailing_blank_lines(), 3);
+
+    editor.screen_cols = 80;
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar_str = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar_str.contains("(3 trailing blank lines)"));
+
+    editor.process_keypress(EditorKey::Meta('t')).unwrap();
+    assert_eq!(editor.status_msg, "Removed 3 trailing blank lines");
+    assert_eq!(editor.buffer().rows.len(), 1);
+    assert_eq!(editor.trailing_blank_lines(), 0);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "text"
+    );
+    assert_eq!(editor.buffer().cursor_y, 1);
+    assert_eq!(editor.buffer().cursor_x, 0);
+
+    let mut output = vec![];
+    editor.write_rows(&mut output).unwrap();
+    assert_eq!(output, b"text\n");
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["text", "", " \t", " \t"]);
+    assert_eq!(editor.trailing_blank_lines(), 3);
+    editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+    assert_eq!(row_lines(&editor), ["text"]);
+    assert_eq!(editor.trailing_blank_lines(), 0);
+    editor.process_keypress(EditorKey::Meta('t')).unwrap();
+    assert_eq!(editor.status_msg, "No trailing blank lines");
+
+    // NOTE: a buffer of blank lines only
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row(vec![' ']);
+    editor.process_keypress(EditorKey::Meta('t')).unwrap();
+    assert_eq!(editor.status_msg, "Removed 1 trailing blank line");
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    editor.append_row(vec![]);
+    editor.process_keypress(EditorKey::Meta('t')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), [" ", ""]);
+    editor.process_keypress(EditorKey::Ctrl('y')).unwrap();
+    assert!(editor.buffer().rows.is_empty());
+}
+
+#[test]
+fn test_sticky_scroll() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.config.sticky_scroll = true;
+
+    for _ in 0..10 {
+        send_test_string(&mut editor, &"0123456789".repeat(10)).unwrap();
+        editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    }
+    send_test_string(&mut editor, "short").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+
+    editor.buffer_mut().cursor_y = 0;
+    editor.buffer_mut().cursor_x = 0;
+    for _ in 0..80 {
+        editor.process_keypress(EditorKey::ArrowRight).unwrap();
+        editor.refresh_screen().unwrap();
+    }
+    let col_offset = editor.buffer().col_offset;
+    assert!(col_offset > 0);
+
+    for _ in 0..10 {
+        editor.process_keypress(EditorKey::ArrowDown).unwrap();
+        editor.refresh_screen().unwrap();
+        assert_eq!(editor.buffer().col_offset, col_offset);
+    }
+    assert_eq!(editor.buffer().cursor_y, 10);
+    assert_eq!(editor.buffer().cursor_x, 5);
+
+    editor.process_keypress(EditorKey::PageUp).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(editor.buffer().col_offset, col_offset);
+
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(editor.buffer().col_offset, 0);
+
+    editor.config.sticky_scroll = false;
+    editor.buffer_mut().cursor_y = 9;
+    editor.buffer_mut().cursor_x = 80;
+    editor.refresh_screen().unwrap();
+    assert_eq!(editor.buffer().col_offset, col_offset);
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(editor.buffer().col_offset, 5);
+}
+
+#[test]
+fn test_line_length_limit() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.config.line_length_limit = Some(5);
+    send_test_string(&mut editor, "0123456789").unwrap();
+
+    let mut output = vec![];
+    editor.scroll();
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\x1b[39m01234\x1b[41m56789\x1b[39m\x1b[49m"));
+
+    editor.buffer_mut().mark = Some((7, 0));
+    editor.buffer_mut().cursor_x = 9;
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("01234\x1b[41m56\x1b[100m78\x1b[41m9"));
+
+    editor.config.line_length_limit = None;
+    editor.buffer_mut().mark = None;
+    let mut output = vec![];
+    editor.draw_rows(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains("\x1b[41m"));
 }
 
 #[test]
@@ -390,17 +2103,20 @@ fn test_find() {
     editor.process_keypress(EditorKey::ArrowUp).unwrap();
     editor.process_keypress(EditorKey::ArrowUp).unwrap();
 
-    assert_eq!(editor.cursor_x, 0);
-    assert_eq!(editor.cursor_y, 0);
+    assert_eq!(editor.buffer().cursor_x, 0);
+    assert_eq!(editor.buffer().cursor_y, 0);
 
     let stdin = b"line\x06\x0d"; // "line", ctrl-f, enter
     editor.stdin = Box::new(&stdin[..]);
     editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
 
-    assert_eq!(editor.cursor_x, 12);
-    assert_eq!(editor.cursor_y, 1);
+    assert_eq!(editor.buffer().cursor_x, 12);
+    assert_eq!(editor.buffer().cursor_y, 1);
     assert_eq!(
-        editor.rows[editor.cursor_y].line.iter().collect::<String>(),
+        editor.buffer().rows[editor.buffer().cursor_y]
+            .line
+            .iter()
+            .collect::<String>(),
         "more text @ line 2"
     );
 
@@ -408,15 +2124,225 @@ fn test_find() {
     editor.stdin = Box::new(&stdin[..]);
     editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
 
-    assert_eq!(editor.cursor_x, 5);
-    assert_eq!(editor.cursor_y, 1);
+    assert_eq!(editor.buffer().cursor_x, 5);
+    assert_eq!(editor.buffer().cursor_y, 1);
 
     let stdin = b"4\x06"; // "4", ctrl-f, escape
     editor.stdin = Box::new(&stdin[..]);
     editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
 
-    assert_eq!(editor.cursor_x, 5);
-    assert_eq!(editor.cursor_y, 1);
+    assert_eq!(editor.buffer().cursor_x, 5);
+    assert_eq!(editor.buffer().cursor_y, 1);
+}
+
+#[test]
+fn test_soft_tabs() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.tab_stop = 4;
+
+    editor.process_keypress(EditorKey::Ctrl('i')).unwrap();
+    send_test_string(&mut editor, "a").unwrap();
+    assert_eq!(row_lines(&editor), ["\ta"]);
+
+    editor.process_keypress(EditorKey::Meta('i')).unwrap();
+    assert!(editor.soft_tabs);
+    editor.process_keypress(EditorKey::Ctrl('i')).unwrap();
+    assert_eq!(row_lines(&editor), ["\ta   "]);
+    send_test_string(&mut editor, "b").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('i')).unwrap();
+    assert_eq!(row_lines(&editor), ["\ta   b   "]);
+
+    let mut status_bar = vec![];
+    editor.screen_cols = 80;
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar.contains("no ft | UTF-8 LF | spaces | 1/1"));
+
+    let (config, _) = Config::parse("soft_tabs = on");
+    assert!(Editor::headless(config).soft_tabs);
+}
+
+#[test]
+fn test_goto_line() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in 1..=100 {
+        editor.append_row(line.to_string().chars().collect());
+    }
+    editor.buffer_mut().cursor_x = 1;
+
+    editor.stdin = Box::new(&b"80\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('g')).unwrap();
+    assert_eq!(
+        (editor.buffer().cursor_x, editor.buffer().cursor_y),
+        (0, 79)
+    );
+
+    editor.stdin = Box::new(&b"101\r"[..]);
+    editor.process_keypress(EditorKey::Meta('g')).unwrap();
+    assert_eq!(
+        (editor.buffer().cursor_x, editor.buffer().cursor_y),
+        (0, 99)
+    );
+
+    editor.stdin = Box::new(&b"0\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('g')).unwrap();
+    assert_eq!(editor.buffer().cursor_y, 99);
+    assert_eq!(editor.status_msg, "`0` isn't a line number");
+
+    editor.stdin = Box::new(&b"80\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('g')).unwrap();
+
+    editor.stdin = Box::new(&b"1x\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('g')).unwrap();
+    assert_eq!(editor.buffer().cursor_y, 79);
+    assert_eq!(editor.status_msg, "`1x` isn't a line number");
+
+    // NOTE: ESC returns to where we were after previewing line 3
+    editor.stdin = Box::new(&b"3\x1b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('g')).unwrap();
+    assert_eq!(editor.buffer().cursor_y, 79);
+}
+
+#[test]
+fn test_find_small_buffers() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.stdin = Box::new(&b"x\x06\x1b[D\x0d"[..]);
+    editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 0));
+
+    editor.append_row("a needle".chars().collect());
+    editor.stdin = Box::new(&b"needle\x06\x06\x1b[A\x0d"[..]);
+    editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (2, 0));
+
+    // NOTE: state left over from a longer buffer is ignored
+    editor.last_match = Some(5);
+    editor.stored_hl = vec![(3, vec![])];
+    let needle: Vec<char> = "needle".chars().collect();
+    editor_find_callback(&mut editor, &needle, EditorKey::ArrowDown);
+    assert_eq!(editor.last_match, Some(0));
+    editor_find_callback(&mut editor, &needle, EditorKey::ArrowUp);
+    assert_eq!(editor.last_match, Some(0));
+}
+
+#[test]
+fn test_find_after_tab() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.append_row("\tx 中 bar".chars().collect());
+
+    let needle: Vec<char> = "bar".chars().collect();
+    editor_find_callback(&mut editor, &needle, EditorKey::Other('r'));
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (5, 0));
+
+    // NOTE: the line is searched, the highlight is set on the render
+    let highlights = &editor.buffer().rows[0].highlights;
+    assert_eq!(highlights.len(), 16);
+    assert!(highlights[13..].iter().all(|hl| *hl == Highlight::Match));
+    assert!(!highlights[..13].contains(&Highlight::Match));
+
+    editor_find_callback(&mut editor, &needle, EditorKey::Ctrl('m'));
+    assert!(!editor.buffer().rows[0]
+        .highlights
+        .contains(&Highlight::Match));
+}
+
+#[test]
+fn test_find_multiline() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["foo", "let x = 1;", "", "end", "x = 1;"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    // NOTE: C-j adds a newline to the search
+    editor.stdin = Box::new(&b"1;\n\nend\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (8, 1));
+
+    let needle: Vec<char> = "x = 1;\n\nE".chars().collect();
+    editor_find_callback(&mut editor, &needle, EditorKey::Other('E'));
+    assert_eq!(editor.last_match, None);
+    editor.search_case_insensitive = true;
+    editor_find_callback(&mut editor, &needle, EditorKey::Other('E'));
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (4, 1));
+
+    let matched = |editor: &Editor, y: usize| {
+        editor.buffer().rows[y]
+            .highlights
+            .contains(&Highlight::Match)
+    };
+    assert!(
+        !editor.buffer().rows[1].highlights[..4].contains(&Highlight::Match)
+    );
+    assert!(matched(&editor, 1) && matched(&editor, 3));
+    assert_eq!(editor.buffer().rows[3].highlights[1], Highlight::Normal);
+
+    editor_find_callback(&mut editor, &needle, EditorKey::Ctrl('m'));
+    assert!(!matched(&editor, 1) && !matched(&editor, 3));
+
+    // NOTE: the first part has to reach the end of the line
+    let needle: Vec<char> = "x\n".chars().collect();
+    editor_find_callback(&mut editor, &needle, EditorKey::Other('\n'));
+    assert_eq!(editor.last_match, None);
+}
+
+#[test]
+fn test_find_ignore_case() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["Error here", "an error", "ERROR"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.stdin = Box::new(&b"error\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (3, 1));
+
+    editor.stdin = Box::new(&b"error\t\r"[..]);
+    editor.process_keypress(EditorKey::Ctrl('f')).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (0, 0));
+    assert!(!editor.search_case_insensitive);
+
+    editor.stdin = Box::new(&b"\terror\rerr\ra"[..]);
+    editor.process_keypress(EditorKey::Ctrl('r')).unwrap();
+    assert_eq!(row_lines(&editor), ["err here", "an err", "err"]);
+    assert!(!editor.search_case_insensitive);
+}
+
+#[test]
+fn test_replace() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["foo bar foo", "foo", "\tfoo foo"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.stdin = Box::new(&b"foo\rquux\rnya"[..]);
+    editor.process_keypress(EditorKey::Ctrl('r')).unwrap();
+    assert_eq!(row_lines(&editor), ["foo bar quux", "quux", "\tquux quux"]);
+    assert_eq!(editor.status_msg, "Replaced 4 occurrences");
+    assert_eq!(
+        (editor.buffer().cursor_x, editor.buffer().cursor_y),
+        (10, 2)
+    );
+    assert!(editor.buffer().dirty);
+
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["foo bar foo", "foo", "\tfoo foo"]);
+
+    // NOTE: start at the second match and wrap around, ESC stops
+    editor.stdin = Box::new(&b"foo\x1b[B\rx\ryyyy\x1b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('r')).unwrap();
+    assert_eq!(row_lines(&editor), ["x bar foo", "x", "\tx x"]);
+    assert_eq!(editor.status_msg, "Replaced 4 occurrences");
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (6, 0));
+
+    editor.stdin = Box::new(&b"foo\x1b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('r')).unwrap();
+    assert_eq!(row_lines(&editor), ["x bar foo", "x", "\tx x"]);
+
+    editor.stdin = Box::new(&b"x\ry\ra"[..]);
+    editor.process_keypress(EditorKey::Meta('r')).unwrap();
+    assert_eq!(row_lines(&editor), ["y bar foo", "y", "\ty y"]);
+    editor.process_keypress(EditorKey::Ctrl('z')).unwrap();
+    assert_eq!(row_lines(&editor), ["x bar foo", "x", "\tx x"]);
 }
 
 #[test]
@@ -424,34 +2350,506 @@ fn test_open_file() {
     let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
     let file = test_file("nonexistent.txt");
     assert!(editor.open(&PathBuf::from(file)).is_ok());
-    assert!(editor.rows.is_empty());
-    assert_eq!(editor.syntax, None);
+    assert!(editor.buffer().rows.is_empty());
+    assert_eq!(editor.buffer().syntax, None);
 
     let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
     let file = test_file("simple.txt");
     assert!(editor.open(&PathBuf::from(file)).is_ok());
-    assert_eq!(editor.rows.len(), 3);
-    assert_eq!(editor.syntax, None);
+    assert_eq!(editor.buffer().rows.len(), 3);
+    assert_eq!(editor.buffer().syntax, None);
 
-    assert_eq!(editor.rows[0].line.iter().collect::<String>(), "ABC");
-    assert_eq!(editor.rows[1].line.iter().collect::<String>(), "DEF");
-    assert_eq!(editor.rows[2].line.iter().collect::<String>(), "GHI");
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "ABC"
+    );
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "DEF"
+    );
+    assert_eq!(
+        editor.buffer().rows[2].line.iter().collect::<String>(),
+        "GHI"
+    );
 
     let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
     let file = test_file("rust_sample.rs");
     assert!(editor.open(&PathBuf::from(file)).is_ok());
-    assert_eq!(editor.rows.len(), 3);
-    assert_eq!(editor.syntax, Some(&SYNTAX_RUST));
+    assert_eq!(editor.buffer().rows.len(), 3);
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
 
     assert_eq!(
-        editor.rows[0].line.iter().collect::<String>(),
+        editor.buffer().rows[0].line.iter().collect::<String>(),
         "fn main() {"
     );
     assert_eq!(
-        editor.rows[1].line.iter().collect::<String>(),
+        editor.buffer().rows[1].line.iter().collect::<String>(),
         "    println!(\"hello world\");"
     );
-    assert_eq!(editor.rows[2].line.iter().collect::<String>(), "}");
+    assert_eq!(editor.buffer().rows[2].line.iter().collect::<String>(), "}");
+}
+
+#[test]
+fn test_load_text() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+
+    editor.load_text("fn main() {}\r\n\nlast");
+    assert_eq!(row_lines(&editor), ["fn main() {}", "", "last"]);
+    assert!(editor.buffer().dirty);
+    assert!(editor.buffer().file.is_none());
+    assert!(editor.buffer().syntax.is_none());
+}
+
+#[test]
+fn test_set_filetype() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    let file = test_file("simple.txt");
+    editor.open(&PathBuf::from(file)).unwrap();
+    assert_eq!(editor.buffer().syntax, None);
+
+    editor.stdin = Box::new(&b"cobol\r"[..]);
+    editor.process_keypress(EditorKey::Meta(':')).unwrap();
+    assert_eq!(editor.buffer().syntax, None);
+
+    editor.stdin = Box::new(&b"rust\r"[..]);
+    editor.process_keypress(EditorKey::Meta(':')).unwrap();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
+    assert_eq!(editor.buffer().rows[0].highlights.len(), 3);
+}
+
+#[test]
+fn test_config_filetypes() {
+    let (config, warnings) = Config::parse(
+        "# comment\n\
+         [filetypes]\n\
+         .txt = haskell\n\
+         Makefile* = shell\n\
+         .h = cobol\n\
+         [unknown]\n\
+         foo\n",
+    );
+    assert_eq!(config.filetypes.len(), 2);
+    assert_eq!(
+        warnings,
+        vec![
+            "line 5: unknown filetype `cobol`",
+            "line 6: unknown section `unknown`",
+            "line 7: expected `key = value`",
+        ]
+    );
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.config = config;
+    editor
+        .open(&PathBuf::from(test_file("simple.txt")))
+        .unwrap();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_HASKELL));
+
+    editor.buffer_mut().file = Some(PathBuf::from("src/Makefile.am"));
+    editor.select_syntax_highlight();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_SHELL));
+
+    editor.buffer_mut().file = Some(PathBuf::from("main.rs"));
+    editor.select_syntax_highlight();
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
+}
+
+#[test]
+fn test_config_colors() {
+    let (config, warnings) = Config::parse(
+        "[colors]\n\
+         keyword = 1;31\n\
+         string = #ff00ff\n\
+         bogus = 3\n\
+         comment = red\n\
+         number = #12345\n",
+    );
+    assert_eq!(
+        config.colors,
+        vec![
+            ("keyword".to_owned(), "1;31".to_owned()),
+            ("string".to_owned(), "38;2;255;0;255".to_owned()),
+        ]
+    );
+    assert_eq!(
+        warnings,
+        vec![
+            "line 4: unknown color `bogus`",
+            "line 5: expected `#rrggbb` or SGR codes, got `red`",
+            "line 6: expected `#rrggbb` or SGR codes, got `#12345`",
+        ]
+    );
+
+    let (general, warnings) = Config::parse("keyword=33\nstring=#ff00ff\n");
+    assert!(warnings.is_empty());
+    assert_eq!(
+        general.colors,
+        vec![
+            ("keyword".to_owned(), "33".to_owned()),
+            ("string".to_owned(), "38;2;255;0;255".to_owned()),
+        ]
+    );
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.theme = crate::THEMES[0].with_colors(&config.colors);
+    editor
+        .open(&PathBuf::from(test_file("rust_sample.rs")))
+        .unwrap();
+
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, true).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("\x1b[22;24;1;31mfn\x1b[39m main() {"));
+    assert!(output.contains("\x1b[22;24;38;2;255;0;255m\"hello world\""));
+}
+
+#[test]
+fn test_color_depth() {
+    use crate::ColorDepth;
+
+    assert_eq!(
+        ColorDepth::detect("truecolor", "xterm"),
+        ColorDepth::TrueColor
+    );
+    assert_eq!(
+        ColorDepth::detect("", "xterm-256color"),
+        ColorDepth::Ansi256
+    );
+    assert_eq!(ColorDepth::detect("", "linux"), ColorDepth::Basic);
+
+    // NOTE: the basic colors keep the terminal's palette
+    let theme = crate::find_theme("high-contrast").unwrap();
+    assert_eq!(theme.with_depth(ColorDepth::Basic), *theme);
+    assert_eq!(theme.with_depth(ColorDepth::Ansi256), *theme);
+    assert_eq!(theme.with_depth(ColorDepth::TrueColor), *theme);
+    let colors = [("keyword".to_string(), "38;2;240;210;150".to_string())];
+    let rich = theme.with_colors(&colors).with_depth(ColorDepth::Ansi256);
+    assert_eq!(rich.keyword.as_ref(), b"\x1b[22;24;38;5;222m");
+    assert_eq!(rich.normal.as_ref(), b"\x1b[22;39m");
+
+    let convert = crate::convert_sgr;
+    assert_eq!(
+        convert(b"\x1b[38;5;31m", ColorDepth::TrueColor),
+        b"\x1b[38;5;31m"
+    );
+    let magenta = b"\x1b[22;24;38;2;255;0;255m";
+    assert_eq!(convert(magenta, ColorDepth::TrueColor), magenta);
+    assert_eq!(
+        convert(magenta, ColorDepth::Ansi256),
+        b"\x1b[22;24;38;5;201m"
+    );
+    assert_eq!(convert(magenta, ColorDepth::Basic), b"\x1b[22;24;95m");
+    assert_eq!(convert(b"\x1b[1;93m", ColorDepth::TrueColor), b"\x1b[1;93m");
+    assert_eq!(
+        convert(b"\x1b[1;38;2;0;0m", ColorDepth::Basic),
+        b"\x1b[1;38;2;0;0m"
+    );
+}
+
+#[test]
+fn test_remember_positions() {
+    let dir = tempfile::tempdir().unwrap();
+    let positions = dir.path().join("red").join("positions");
+    let file = dir.path().join("file.txt");
+    std::fs::write(&file, "a\nbcd\nef\n").unwrap();
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(&file).unwrap();
+    (editor.buffer_mut().cursor_x, editor.buffer_mut().cursor_y) = (2, 1);
+    editor.remember_positions();
+    assert!(!positions.exists());
+
+    editor.positions = Some(positions.clone());
+    editor.remember_positions();
+    let other = dir.path().join("other.txt");
+    crate::red_positions::store(&positions, &other, (5, 7)).unwrap();
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.positions = Some(positions.clone());
+    editor.open(&file).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (2, 1));
+
+    // NOTE: positions past the end of a shorter file are clamped
+    std::fs::write(&file, "a\nb\n").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.positions = Some(positions.clone());
+    editor.open(&file).unwrap();
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (1, 1));
+
+    let content = std::fs::read_to_string(&positions).unwrap();
+    let lines = content.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], format!("7\t5\t{}", other.display()));
+    assert_eq!(
+        crate::red_positions::lookup(&positions, &other),
+        Some((5, 7))
+    );
+}
+
+#[test]
+fn test_cat_missing_file() {
+    let theme = crate::THEMES[0].clone();
+    let files = [PathBuf::from("does/not/exist.rs")];
+    let err = crate::cat(&files, Config::default(), RED_TAB_STOP, theme);
+    assert!(err
+        .unwrap_err()
+        .to_string()
+        .starts_with("does/not/exist.rs: "));
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("*.h", "test.h"));
+    assert!(glob_match("Makefile*", "Makefile"));
+    assert!(glob_match("?ake*.*", "Makefile.in"));
+    assert!(glob_match("*a*b*", "xxaxxbxx"));
+    assert!(!glob_match("*.h", "test.hpp"));
+    assert!(!glob_match("a?", "a"));
+}
+
+#[test]
+fn test_write_highlighted() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor
+        .open(&PathBuf::from(test_file("rust_sample.rs")))
+        .unwrap();
+
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, true).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let first_line = output.lines().next().unwrap();
+    assert_eq!(first_line, "\x1b[33mfn\x1b[39m main() {\x1b[39m");
+    assert!(output.contains("\x1b[35m\"hello world\"\x1b[39m"));
+
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, false).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "fn main() {\n    println!(\"hello world\");\n}\n"
+    );
+
+    editor.theme = crate::find_theme("monochrome").unwrap().clone();
+    let mut output = vec![];
+    editor.write_highlighted(&mut output, true).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let first_line = output.lines().next().unwrap();
+    assert_eq!(first_line, "\x1b[24;1mfn\x1b[22;24m main() {\x1b[22;24m");
+}
+
+#[test]
+fn test_lazy_highlighting() {
+    let mut file = tempfile::Builder::new().suffix(".c").tempfile().unwrap();
+    writeln!(file, "/*").unwrap();
+    for _ in 0..100_000 {
+        writeln!(file, "x").unwrap();
+    }
+    writeln!(file, "*/\nint y;").unwrap();
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(
+        editor.buffer().rows[10].highlights,
+        [Highlight::MultiLineComment]
+    );
+    assert!(editor.buffer().rows[100].highlights.is_empty());
+
+    // NOTE: the comment starts far above the screen
+    editor.buffer_mut().cursor_y = editor.buffer().rows.len() - 1;
+    editor.refresh_screen().unwrap();
+    assert_eq!(
+        editor.buffer().rows[100_001].highlights[1],
+        Highlight::MultiLineComment
+    );
+    assert_eq!(editor.buffer().rows[100_002].highlights[0], Highlight::Type);
+}
+
+#[test]
+fn test_split_window() {
+    let stdin = b"";
+    let mut editor = dummy_editor(Box::new(&stdin[..]), Box::new(vec![]));
+    editor
+        .open(&PathBuf::from(test_file("simple.txt")))
+        .unwrap();
+    editor
+        .open_buffer(&PathBuf::from(test_file("rust_sample.rs")))
+        .unwrap();
+    editor.switch_buffer(0);
+    editor.split_window(1);
+
+    assert_eq!(editor.current_buffer, 0);
+    assert_eq!(editor.pane_cols(), 30);
+    assert_eq!(editor.pane_left(), 0);
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "ABC"
+    );
+    editor.process_keypress(EditorKey::ArrowDown).unwrap();
+
+    editor.stdin = Box::new(&b"o"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 1);
+    assert_eq!(editor.pane_cols(), 29);
+    assert_eq!(editor.pane_left(), 31);
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
+    assert_eq!(editor.buffer().cursor_y, 0);
+    send_test_string(&mut editor, "// ").unwrap();
+    assert_eq!(
+        editor.buffer().rows[0].line.iter().collect::<String>(),
+        "// fn main() {"
+    );
+
+    let mut output = vec![];
+    editor.draw_split(&mut output).unwrap();
+    assert_eq!(editor.current_buffer, 1);
+    assert_eq!(editor.buffer().cursor_x, 3);
+    assert_eq!(editor.render_x, 3);
+
+    let output = String::from_utf8(output).unwrap();
+    let first_line = output.lines().next().unwrap();
+    assert!(first_line.contains("ABC"));
+    assert!(first_line.contains("\x1b[1;31H│"));
+    assert!(first_line.contains("// fn main() {"));
+    assert!(first_line.find("ABC") < first_line.find("\x1b[1;31H"));
+
+    // NOTE: a click on the separator keeps the focus
+    editor.click((30, 0), true, false);
+    assert_eq!(editor.current_buffer, 1);
+    assert_eq!(editor.buffer().cursor_x, 3);
+
+    editor.stdin = Box::new(&b"o"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 0);
+    assert_eq!(editor.buffer().cursor_y, 1);
+    assert!(!editor.buffer().dirty);
+    assert!(editor.is_any_buffer_dirty());
+}
+
+#[test]
+fn test_split_view() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for idx in 0..100 {
+        editor.append_row(idx.to_string().chars().collect());
+    }
+
+    editor.stdin = Box::new(&b"2"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!((editor.pane_top(), editor.pane_rows()), (0, 24));
+    editor.process_keypress(EditorKey::PageDown).unwrap();
+    editor.process_keypress(EditorKey::PageDown).unwrap();
+    editor.refresh_screen().unwrap();
+    assert_eq!(
+        (editor.buffer().cursor_y, editor.buffer().row_offset),
+        (47, 24)
+    );
+
+    editor.stdin = Box::new(&b"o"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!((editor.pane_top(), editor.pane_rows()), (25, 23));
+    assert_eq!(
+        (editor.buffer().cursor_y, editor.buffer().row_offset),
+        (0, 0)
+    );
+    send_test_string(&mut editor, "x").unwrap();
+
+    // NOTE: both panes show the same rows
+    let mut output = vec![];
+    editor.draw_hsplit(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<_> = output.split("\r\n").collect();
+    assert!(lines[0].contains("24"));
+    assert!(lines[24].contains("(modified)"));
+    assert!(lines[25].contains("x0"));
+    assert_eq!(editor.pane_top(), 25);
+
+    // NOTE: a click on the status line in between keeps the focus
+    editor.click((5, 24), true, false);
+    assert_eq!((editor.pane_top(), editor.buffer().cursor_y), (25, 0));
+
+    // NOTE: a click in the other pane moves the focus there
+    editor.click((5, 3), true, false);
+    assert_eq!((editor.pane_top(), editor.buffer().cursor_y), (0, 27));
+
+    editor.stdin = Box::new(&b"1"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!((editor.pane_top(), editor.pane_rows()), (0, 48));
+    assert_eq!(editor.buffer().cursor_y, 27);
+}
+
+#[test]
+fn test_buffers() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    let simple = test_file("simple.txt");
+    editor.open(&PathBuf::from(&simple)).unwrap();
+
+    editor.stdin = Box::new(&b"b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.status_msg, "There is no other buffer");
+
+    let input = format!("\x06{}\r", test_file("rust_sample.rs"));
+    editor.stdin = Box::new(Cursor::new(input.into_bytes()));
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 1);
+    assert_eq!(editor.buffer().syntax, Some(&SYNTAX_RUST));
+
+    let mut status_bar = vec![];
+    editor.draw_status_bar(&mut status_bar).unwrap();
+    let status_bar = String::from_utf8(status_bar).unwrap();
+    assert!(status_bar.contains("(buffer 2/2) - 3 lines"));
+
+    // NOTE: opening a file again switches to its buffer
+    let input = format!("\x06{}\r", simple);
+    editor.stdin = Box::new(Cursor::new(input.into_bytes()));
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 0);
+    assert_eq!(editor.buffers.len(), 2);
+
+    editor.stdin = Box::new(&b"b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 1);
+    editor.stdin = Box::new(&b"b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 0);
+    assert_eq!(editor.buffer().file, Some(PathBuf::from(simple)));
+
+    // NOTE: the buffer of the other window isn't shown twice
+    editor.split_window(1);
+    editor.stdin = Box::new(&b"b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    assert_eq!(editor.current_buffer, 0);
+}
+
+#[test]
+fn test_yank_pop_after_buffer_switch() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    send_test_string(&mut editor, "one").unwrap();
+    editor.process_keypress(EditorKey::Ctrl('m')).unwrap();
+    send_test_string(&mut editor, "two").unwrap();
+    editor.process_keypress(EditorKey::Home).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+    editor.process_keypress(EditorKey::ArrowUp).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('k')).unwrap();
+
+    let input = format!("\x06{}\r", test_file("simple.txt"));
+    editor.stdin = Box::new(Cursor::new(input.into_bytes()));
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    let simple = row_lines(&editor);
+
+    editor.stdin = Box::new(&b"b"[..]);
+    editor.process_keypress(EditorKey::Ctrl('x')).unwrap();
+    editor.process_keypress(EditorKey::Ctrl('v')).unwrap();
+    assert_eq!(row_lines(&editor), ["one", ""]);
+    assert!(editor.last_yank.is_some());
+
+    // NOTE: the yanked range belongs to the previous buffer
+    editor.switch_buffer(1);
+    assert_eq!(editor.last_yank, None);
+    editor.process_keypress(EditorKey::Meta('y')).unwrap();
+    assert_eq!(editor.status_msg, "Previous command was not a paste");
+    assert_eq!(row_lines(&editor), simple);
+
+    editor.switch_buffer(0);
+    assert_eq!(row_lines(&editor), ["one", ""]);
 }
 
 #[test]
@@ -462,20 +2860,158 @@ fn test_save_file() {
 
     write_editor.open(&file_path).unwrap();
     send_test_string(&mut write_editor, "this is a test").unwrap();
-    assert_eq!(write_editor.dirty, true);
+    assert!(write_editor.buffer().dirty);
     write_editor.save().unwrap();
-    assert_eq!(write_editor.dirty, false);
+    assert!(!write_editor.buffer().dirty);
 
     let mut read_editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
     read_editor.open(&file_path).unwrap();
-    assert_eq!(read_editor.rows.len(), 1);
+    assert_eq!(read_editor.buffer().rows.len(), 1);
 
     assert_eq!(
-        read_editor.rows[0].line.iter().collect::<String>(),
+        read_editor.buffer().rows[0].line.iter().collect::<String>(),
         "this is a test"
     );
 }
 
+#[test]
+fn test_autosave() {
+    let file = NamedTempFile::new().unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    send_test_string(&mut editor, "draft").unwrap();
+    assert!(!editor.maybe_autosave().unwrap());
+
+    let (config, _) =
+        Config::parse("autosave_secs = 30\nstrip_trailing_whitespace = yes");
+    editor.config = config;
+    assert!(!editor.maybe_autosave().unwrap());
+
+    // NOTE: not in the middle of a prompt
+    editor.last_keypress -= Duration::from_secs(30);
+    assert!(!editor.maybe_autosave().unwrap());
+    editor.reading_command = true;
+    assert!(editor.maybe_autosave().unwrap());
+    assert_eq!(editor.status_msg, "Autosave: 6 bytes written to disk");
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "draft\n");
+    assert!(!editor.maybe_autosave().unwrap());
+
+    // NOTE: trailing whitespace is only stripped by an explicit save
+    send_test_string(&mut editor, " ").unwrap();
+    editor.last_keypress -= Duration::from_secs(30);
+    assert!(editor.maybe_autosave().unwrap());
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "draft \n");
+
+    // NOTE: buffers without a file are never saved on their own
+    editor.buffer_mut().file = None;
+    editor.buffer_mut().dirty = true;
+    assert!(!editor.maybe_autosave().unwrap());
+}
+
+#[test]
+fn test_autosave_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor
+        .open(&dir.path().join("missing").join("file.txt"))
+        .unwrap();
+    send_test_string(&mut editor, "draft").unwrap();
+
+    let (config, _) = Config::parse("autosave_secs = 30");
+    editor.config = config;
+    editor.reading_command = true;
+    editor.last_keypress -= Duration::from_secs(30);
+    assert!(editor.maybe_autosave().unwrap());
+    assert!(editor.status_msg.starts_with("Autosave: Can't save!"));
+    assert!(editor.buffer().dirty);
+    assert!(editor.is_any_buffer_dirty());
+
+    // NOTE: no retry until the next keypress
+    assert!(!editor.maybe_autosave().unwrap());
+    send_test_string(&mut editor, "!").unwrap();
+    editor.last_keypress -= Duration::from_secs(30);
+    assert!(editor.maybe_autosave().unwrap());
+    assert!(editor.buffer().dirty);
+}
+
+#[test]
+fn test_changed_on_disk() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "mine").unwrap();
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "5 bytes written to disk");
+
+    std::fs::write(file.path(), "theirs\n").unwrap();
+    let later = editor.buffer().disk_mtime.unwrap() + Duration::from_secs(1);
+    file.as_file().set_modified(later).unwrap();
+
+    editor.stdin = Box::new(&b"n"[..]);
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "Save aborted");
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "theirs\n");
+
+    editor.stdin = Box::new(&b"y"[..]);
+    editor.save().unwrap();
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "mine\n");
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "5 bytes written to disk");
+
+    // NOTE: an older copy put back in place is a change as well
+    let earlier = editor.buffer().disk_mtime.unwrap() - Duration::from_secs(60);
+    file.as_file().set_modified(earlier).unwrap();
+    editor.stdin = Box::new(&b"n"[..]);
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "Save aborted");
+}
+
+#[test]
+fn test_backup() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "old").unwrap();
+    let mut backup = file.path().as_os_str().to_owned();
+    backup.push("~");
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    editor.buffer_mut().rows[0].line = "new".chars().collect();
+    editor.save().unwrap();
+    assert!(std::fs::metadata(&backup).is_err());
+
+    // NOTE: saves without backups don't count as the backup of the session
+    editor.backup = true;
+    editor.buffer_mut().rows[0].line = "newer".chars().collect();
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "6 bytes written to disk, backup written");
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), "new\n");
+
+    // NOTE: later saves keep the first backup
+    editor.buffer_mut().rows[0].line = "newest".chars().collect();
+    editor.save().unwrap();
+    assert_eq!(editor.status_msg, "7 bytes written to disk");
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), "new\n");
+
+    // NOTE: the file is saved even if the backup can't be written
+    std::fs::remove_file(&backup).unwrap();
+    std::fs::create_dir(&backup).unwrap();
+    editor.buffer_mut().made_backup = false;
+    editor.save().unwrap();
+    assert!(editor.status_msg.contains(", backup failed: "));
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "newest\n");
+    std::fs::remove_dir(&backup).unwrap();
+
+    // NOTE: new files have nothing to back up
+    let path = file.path().with_extension("new");
+    editor.buffer_mut().file = Some(path.clone());
+    editor.buffer_mut().made_backup = false;
+    editor.save().unwrap();
+    assert!(!editor.status_msg.contains("backup"));
+    editor.save().unwrap();
+    assert!(!editor.status_msg.contains("backup"));
+    std::fs::remove_file(path).unwrap();
+}
+
 #[test]
 fn test_parse_utf8() {
     let input = b"";
@@ -505,3 +3041,109 @@ fn test_parse_utf8() {
         Box::new(EditorError::InvalidUtf8Input)
     );
 }
+
+#[test]
+fn test_follow() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "first\nsecond\nthi").unwrap();
+    file.flush().unwrap();
+
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(file.path()).unwrap();
+    editor.start_follow().unwrap();
+    assert_eq!(editor.buffer().cursor_y, 2);
+    assert!(!editor.maybe_follow().unwrap());
+
+    write!(file, "rd\nfourth\nfifth\n").unwrap();
+    file.flush().unwrap();
+    assert!(editor.maybe_follow().unwrap());
+    let lines = editor
+        .buffer()
+        .rows
+        .iter()
+        .map(|r| r.line.iter().collect::<String>())
+        .collect::<Vec<_>>();
+    assert_eq!(lines, ["first", "second", "third", "fourth", "fifth"]);
+    assert_eq!(editor.buffer().cursor_y, 4);
+
+    editor.buffer_mut().cursor_y = 1;
+    writeln!(file, "sixth").unwrap();
+    file.flush().unwrap();
+    assert!(editor.maybe_follow().unwrap());
+    assert_eq!(editor.buffer().rows.len(), 6);
+    assert_eq!(editor.buffer().cursor_y, 1);
+
+    send_test_string(&mut editor, "x").unwrap();
+    assert!(!editor.buffer().dirty);
+    assert_eq!(
+        editor.buffer().rows[1].line.iter().collect::<String>(),
+        "second"
+    );
+
+    // NOTE: a file that doesn't exist yet is followed once it's created
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("later.log");
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    editor.open(&path).unwrap();
+    editor.start_follow().unwrap();
+    assert!(!editor.maybe_follow().unwrap());
+    std::fs::write(&path, "one\ntwo\n").unwrap();
+    assert!(editor.maybe_follow().unwrap());
+    assert_eq!(row_lines(&editor), ["one", "two"]);
+}
+
+#[test]
+fn test_parse_args() {
+    let parse =
+        |args: &[&str]| crate::Args::parse(args.iter().map(|a| a.to_string()));
+
+    let args = parse(&["-f", "log.txt"]).unwrap();
+    assert!(args.follow);
+    assert_eq!(args.files, [PathBuf::from("log.txt")]);
+    assert!(parse(&["--cat", "a", "b"]).unwrap().cat);
+    assert!(parse(&["-R", "a"]).unwrap().read_only);
+    assert!(parse(&["--follow"]).is_err());
+    assert!(parse(&["--bogus", "a"]).is_err());
+    assert!(parse(&["-"]).unwrap().stdin);
+    assert!(parse(&["--no-backup", "a"]).unwrap().no_backup);
+    assert!(parse(&["--stdout", "-"]).unwrap().stdout);
+    assert!(parse(&["-", "a"]).is_err());
+    let args = parse(&["--theme", "monochrome", "a"]).unwrap();
+    assert_eq!(args.theme.map(|theme| theme.name), Some("monochrome"));
+    assert!(parse(&["--theme", "bogus", "a"]).is_err());
+    assert!(parse(&["a", "--theme"]).is_err());
+    let args = parse(&["--color=256", "a"]).unwrap();
+    assert_eq!(args.color, Some(crate::ColorDepth::Ansi256));
+    assert!(parse(&["--color=1000", "a"]).is_err());
+
+    let args = parse(&["main.rs:42", "lib.rs:0:7", "a:b", ":3"]).unwrap();
+    assert_eq!(
+        args.files,
+        ["main.rs", "lib.rs", "a:b", ":3"].map(PathBuf::from)
+    );
+    assert_eq!(args.positions, [Some((42, 1)), Some((1, 7)), None, None]);
+
+    let args = parse(&["+12", "a", "b", "+3", "c:4", "+x"]).unwrap();
+    assert_eq!(args.files, ["a", "b", "c", "+x"].map(PathBuf::from));
+    assert_eq!(args.positions, [Some((12, 1)), None, Some((4, 1)), None]);
+
+    // NOTE: existing files with a colon in their name are opened as is
+    let file = tempfile::Builder::new().suffix(":12").tempfile().unwrap();
+    let name = file.path().to_str().unwrap();
+    let args = parse(&[name]).unwrap();
+    assert_eq!(args.files, [file.path()]);
+    assert_eq!(args.positions, [None]);
+}
+
+#[test]
+fn test_goto_position() {
+    let mut editor = dummy_editor(Box::new(&b""[..]), Box::new(vec![]));
+    for line in ["first", "second", "third"] {
+        editor.append_row(line.chars().collect());
+    }
+
+    editor.goto_position((2, 4));
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (3, 1));
+    editor.goto_position((usize::MAX, 100));
+    assert_eq!((editor.buffer().cursor_x, editor.buffer().cursor_y), (5, 2));
+}